@@ -1,4 +1,5 @@
-use intcode::{input, run_instruction_set, IntcodeReturnType, Memory};
+use intcode::machine::Machine;
+use intcode::{input, run_instruction_set, Memory, Outcome};
 use itertools::iproduct;
 
 fn main() {
@@ -17,25 +18,26 @@ fn main() {
     println!("valid inputs are: {:?}", valid_values)
 }
 
+const STEP_BUDGET: u64 = 10_000;
+
 fn find_inputs_for(memory: &Memory, wanted_output: i64) -> Vec<(i64, i64)> {
     let mut valid_values = vec![];
+    let mut machine = Machine::new(memory);
     for (noun, verb) in iproduct!(0..99, 0..99) {
-        let code = get_custom_inputs(&memory, noun, verb);
-
-        let intcode = run_instruction_set(code);
-
-        match intcode {
-            IntcodeReturnType::CodeError => {
-                continue;
-            }
-            IntcodeReturnType::IndexError => continue,
-            IntcodeReturnType::Finished(state) => {
-                let output = state.code[0];
+        machine.reset(memory);
+        machine.set_step_budget(Some(STEP_BUDGET));
+        machine.memory_mut()[1] = noun;
+        machine.memory_mut()[2] = verb;
+
+        match machine.run() {
+            Err(_) | Ok(Outcome::BudgetExhausted) => continue,
+            Ok(Outcome::Halted) => {
+                let output = machine.memory()[0];
                 if output == wanted_output {
                     valid_values.push((noun, verb))
                 }
             }
-            IntcodeReturnType::Interrupted(_) => {
+            Ok(Outcome::WaitingForInput) | Ok(Outcome::Continue) => {
                 unreachable!("day two does not have interrupts");
             }
         }
@@ -0,0 +1,440 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use intcode::machine::Machine;
+use intcode::{intcode_step, IntcodeReturnType, IntcodeState, Memory, Stop};
+
+fn parse(source: &str) -> Memory {
+    source
+        .split(',')
+        .map(|word| word.trim().parse().unwrap())
+        .collect()
+}
+
+// A private copy of the interpreter as it was before `Machine`: every step
+// takes the whole state by value and hands it back inside a `Result`
+mod baseline {
+    use std::collections::VecDeque;
+    use std::convert::TryInto;
+
+    pub struct State {
+        pub code: Vec<i64>,
+        pub index: usize,
+        relative_base: i64,
+        input: VecDeque<i64>,
+        pub output: Vec<i64>,
+        memory_limit: usize,
+        step_budget: Option<u64>,
+    }
+
+    pub struct Error {
+        pub pc: usize,
+        pub instruction: i64,
+        pub parameter: Option<usize>,
+        pub kind: &'static str,
+    }
+
+    pub enum Stop {
+        Finished(State),
+        Interrupted(State),
+        BudgetExhausted(State),
+        Failed(Error),
+    }
+
+    impl From<Error> for Stop {
+        fn from(error: Error) -> Self {
+            Stop::Failed(error)
+        }
+    }
+
+    enum Mode {
+        Position,
+        Immediate,
+        Relative,
+    }
+
+    enum Op {
+        Add(Mode, Mode, Mode),
+        Mul(Mode, Mode, Mode),
+        Input(Mode),
+        Output(Mode),
+        JumpIfTrue(Mode, Mode),
+        JumpIfFalse(Mode, Mode),
+        LessThan(Mode, Mode, Mode),
+        Equals(Mode, Mode, Mode),
+        AdjustRelativeBase(Mode),
+    }
+
+    impl State {
+        pub fn new(code: Vec<i64>, input: Vec<i64>) -> State {
+            State {
+                code,
+                index: 0,
+                relative_base: 0,
+                input: input.into(),
+                output: vec![],
+                memory_limit: 1 << 24,
+                step_budget: None,
+            }
+        }
+
+        fn error(&self, kind: &'static str) -> Error {
+            Error {
+                pc: self.index,
+                instruction: self.code.get(self.index).copied().unwrap_or(0),
+                parameter: None,
+                kind,
+            }
+        }
+    }
+
+    pub fn run(mut state: State) -> Result<State, Stop> {
+        loop {
+            state = match step(state) {
+                Ok(state) => state,
+                Err(Stop::Finished(state)) => return Ok(state),
+                Err(stop) => return Err(stop),
+            }
+        }
+    }
+
+    fn step(state: State) -> Result<State, Stop> {
+        if state.step_budget == Some(0) {
+            return Err(Stop::BudgetExhausted(state));
+        }
+
+        let mut state = execute(state)?;
+        if let Some(step_budget) = state.step_budget.as_mut() {
+            *step_budget -= 1;
+        }
+
+        Ok(state)
+    }
+
+    fn execute(mut state: State) -> Result<State, Stop> {
+        let index = state.index;
+        let instruction = get_index_value(&state, index)?;
+        if !(0..=99999).contains(&instruction) {
+            return Err(state.error("invalid instruction").into());
+        }
+
+        let mode = |digit| match instruction / digit % 10 {
+            0 => Ok(Mode::Position),
+            1 => Ok(Mode::Immediate),
+            2 => Ok(Mode::Relative),
+            _ => Err(state.error("unknown parameter mode")),
+        };
+        let op = match instruction % 100 {
+            1 => Op::Add(mode(100)?, mode(1000)?, mode(10000)?),
+            2 => Op::Mul(mode(100)?, mode(1000)?, mode(10000)?),
+            3 => Op::Input(mode(100)?),
+            4 => Op::Output(mode(100)?),
+            5 => Op::JumpIfTrue(mode(100)?, mode(1000)?),
+            6 => Op::JumpIfFalse(mode(100)?, mode(1000)?),
+            7 => Op::LessThan(mode(100)?, mode(1000)?, mode(10000)?),
+            8 => Op::Equals(mode(100)?, mode(1000)?, mode(10000)?),
+            9 => Op::AdjustRelativeBase(mode(100)?),
+            99 => return Err(Stop::Finished(state)),
+            _ => return Err(state.error("unknown opcode").into()),
+        };
+
+        match op {
+            Op::Add(mode_1, mode_2, mode_3) => {
+                three_inputs(state, mode_1, mode_2, mode_3, |a, b| a + b)
+            }
+            Op::Mul(mode_1, mode_2, mode_3) => {
+                three_inputs(state, mode_1, mode_2, mode_3, |a, b| a * b)
+            }
+            Op::Input(mode) => {
+                let target = get_target_index(&state, index + 1, &mode)?;
+                let input = match state.input.pop_front() {
+                    Some(input) => input,
+                    None => return Err(Stop::Interrupted(state)),
+                };
+                state.code = set_at_index_location(state.code, target, input);
+                state.index += 2;
+                Ok(state)
+            }
+            Op::Output(mode) => {
+                let output = get_value_at_index_location(&state, index + 1, &mode)?;
+                state.output.push(output);
+                state.index += 2;
+                Ok(state)
+            }
+            Op::JumpIfTrue(mode_1, mode_2) => {
+                match get_value_at_index_location(&state, index + 1, &mode_1)? {
+                    0 => state.index += 3,
+                    _ => state.index = get_jump_target(&state, index + 2, &mode_2)?,
+                }
+                Ok(state)
+            }
+            Op::JumpIfFalse(mode_1, mode_2) => {
+                match get_value_at_index_location(&state, index + 1, &mode_1)? {
+                    0 => state.index = get_jump_target(&state, index + 2, &mode_2)?,
+                    _ => state.index += 3,
+                }
+                Ok(state)
+            }
+            Op::LessThan(mode_1, mode_2, mode_3) => {
+                three_inputs(state, mode_1, mode_2, mode_3, |a, b| (a < b) as i64)
+            }
+            Op::Equals(mode_1, mode_2, mode_3) => {
+                three_inputs(state, mode_1, mode_2, mode_3, |a, b| (a == b) as i64)
+            }
+            Op::AdjustRelativeBase(mode) => {
+                state.relative_base += get_value_at_index_location(&state, index + 1, &mode)?;
+                state.index += 2;
+                Ok(state)
+            }
+        }
+    }
+
+    fn three_inputs(
+        mut state: State,
+        mode_1: Mode,
+        mode_2: Mode,
+        mode_3: Mode,
+        operation: impl Fn(i64, i64) -> i64,
+    ) -> Result<State, Stop> {
+        let index = state.index;
+        let operand_1 = get_value_at_index_location(&state, index + 1, &mode_1)?;
+        let operand_2 = get_value_at_index_location(&state, index + 2, &mode_2)?;
+        let target = get_target_index(&state, index + 3, &mode_3)?;
+        state.code = set_at_index_location(state.code, target, operation(operand_1, operand_2));
+        state.index += 4;
+
+        Ok(state)
+    }
+
+    fn get_index_value(state: &State, index: usize) -> Result<i64, Error> {
+        if index >= state.memory_limit {
+            return Err(state.error("memory limit exceeded"));
+        }
+
+        Ok(state.code.get(index).copied().unwrap_or(0))
+    }
+
+    fn get_target_index(state: &State, index: usize, mode: &Mode) -> Result<usize, Error> {
+        let index_value = get_index_value(state, index)?;
+        let address = match mode {
+            Mode::Position => index_value,
+            Mode::Relative => state.relative_base + index_value,
+            Mode::Immediate => return Err(state.error("immediate write")),
+        };
+        let address: usize = address
+            .try_into()
+            .map_err(|_| state.error("negative address"))?;
+        if address >= state.memory_limit {
+            return Err(state.error("memory limit exceeded"));
+        }
+
+        Ok(address)
+    }
+
+    fn get_value_at_index_location(state: &State, index: usize, mode: &Mode) -> Result<i64, Error> {
+        match mode {
+            Mode::Immediate => get_index_value(state, index),
+            Mode::Position | Mode::Relative => {
+                let address = get_target_index(state, index, mode)?;
+                Ok(state.code.get(address).copied().unwrap_or(0))
+            }
+        }
+    }
+
+    fn get_jump_target(state: &State, index: usize, mode: &Mode) -> Result<usize, Error> {
+        get_value_at_index_location(state, index, mode)?
+            .try_into()
+            .map_err(|_| state.error("invalid jump target"))
+    }
+
+    fn set_at_index_location(mut code: Vec<i64>, target: usize, value: i64) -> Vec<i64> {
+        if target >= code.len() {
+            code.resize(target + 1, 0);
+        }
+        code[target] = value;
+
+        code
+    }
+}
+
+fn run_baseline(state: baseline::State) -> baseline::State {
+    match baseline::run(state) {
+        Ok(state) => state,
+        Err(baseline::Stop::Interrupted(state)) | Err(baseline::Stop::BudgetExhausted(state)) => {
+            panic!("unexpected stop at {}", state.index)
+        }
+        Err(baseline::Stop::Failed(error)) => panic!(
+            "{} at {} (instruction {}, parameter {:?})",
+            error.kind, error.pc, error.instruction, error.parameter
+        ),
+        Err(baseline::Stop::Finished(_)) => unreachable!(),
+    }
+}
+
+// Drives the public `intcode_step` API, which moves the whole state in and out
+// of every step on top of the in-place interpreter
+fn run_stepwise(mut intcode_state: IntcodeState) -> IntcodeState {
+    loop {
+        intcode_state = match intcode_step(intcode_state) {
+            Ok(state) => state,
+            Err(Stop::Returned(IntcodeReturnType::Finished(state))) => return state,
+            Err(stop) => panic!("unexpected stop {:?}", stop),
+        }
+    }
+}
+
+fn day2_search(c: &mut Criterion) {
+    let program = parse(include_str!("../../day2/resources/input"));
+    let mut group = c.benchmark_group("day2 search");
+
+    group.bench_function("by-value baseline", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for noun in 0..20 {
+                for verb in 0..20 {
+                    let mut code = program.clone();
+                    code[1] = noun;
+                    code[2] = verb;
+                    found += run_baseline(baseline::State::new(code, vec![])).code[0];
+                }
+            }
+            black_box(found)
+        })
+    });
+
+    group.bench_function("intcode_step loop", |b| {
+        b.iter(|| {
+            let mut found = 0;
+            for noun in 0..20 {
+                for verb in 0..20 {
+                    let mut code = program.clone();
+                    code[1] = noun;
+                    code[2] = verb;
+                    found += run_stepwise(IntcodeState::from(code)).code[0];
+                }
+            }
+            black_box(found)
+        })
+    });
+
+    group.bench_function("reused machine", |b| {
+        let mut machine = Machine::new(&program);
+        b.iter(|| {
+            let mut found = 0;
+            for noun in 0..20 {
+                for verb in 0..20 {
+                    machine.reset(&program);
+                    machine.memory_mut()[1] = noun;
+                    machine.memory_mut()[2] = verb;
+                    machine.run().unwrap();
+                    found += machine.memory()[0];
+                }
+            }
+            black_box(found)
+        })
+    });
+
+    group.bench_function("reused machine with decode cache", |b| {
+        let mut machine = Machine::new(&program).with_decode_cache();
+        b.iter(|| {
+            let mut found = 0;
+            for noun in 0..20 {
+                for verb in 0..20 {
+                    machine.reset(&program);
+                    machine.memory_mut()[1] = noun;
+                    machine.memory_mut()[2] = verb;
+                    machine.run().unwrap();
+                    found += machine.memory()[0];
+                }
+            }
+            black_box(found)
+        })
+    });
+
+    group.finish();
+}
+
+fn day7_chain(c: &mut Criterion) {
+    let program = parse(include_str!("../../day7/resources/input"));
+    let settings = [2, 1, 4, 3, 0];
+    let mut group = c.benchmark_group("day7 amplifier chain");
+
+    group.bench_function("by-value baseline", |b| {
+        b.iter(|| {
+            let signal = settings.iter().fold(0, |signal, &phase| {
+                let state = baseline::State::new(program.clone(), vec![phase, signal]);
+                *run_baseline(state).output.last().unwrap()
+            });
+            black_box(signal)
+        })
+    });
+
+    group.bench_function("intcode_step loop", |b| {
+        b.iter(|| {
+            let signal = settings.iter().fold(0, |signal, &phase| {
+                let state = IntcodeState::with_inputs(program.clone(), vec![phase, signal]);
+                *run_stepwise(state).output.last().unwrap()
+            });
+            black_box(signal)
+        })
+    });
+
+    group.bench_function("reused machine", |b| {
+        let mut machine = Machine::new(&program);
+        b.iter(|| {
+            let signal = settings.iter().fold(0, |signal, &phase| {
+                machine.reset(&program);
+                machine.push_input(phase);
+                machine.push_input(signal);
+                machine.run().unwrap();
+                machine.last_output().unwrap()
+            });
+            black_box(signal)
+        })
+    });
+
+    group.bench_function("reused machine with decode cache", |b| {
+        let mut machine = Machine::new(&program).with_decode_cache();
+        b.iter(|| {
+            let signal = settings.iter().fold(0, |signal, &phase| {
+                machine.reset(&program);
+                machine.push_input(phase);
+                machine.push_input(signal);
+                machine.run().unwrap();
+                machine.last_output().unwrap()
+            });
+            black_box(signal)
+        })
+    });
+
+    group.finish();
+}
+
+fn long_loop(c: &mut Criterion) {
+    // Counts [13] down from 100000 without any output
+    let program = vec![1001, 13, -1, 13, 1005, 13, 0, 99, 0, 0, 0, 0, 0, 100_000];
+    let mut group = c.benchmark_group("countdown loop");
+
+    group.bench_function("by-value baseline", |b| {
+        b.iter(|| black_box(run_baseline(baseline::State::new(program.clone(), vec![])).code[13]))
+    });
+
+    group.bench_function("reused machine", |b| {
+        let mut machine = Machine::new(&program);
+        b.iter(|| {
+            machine.reset(&program);
+            black_box(machine.run().unwrap())
+        })
+    });
+
+    group.bench_function("reused machine with decode cache", |b| {
+        let mut machine = Machine::new(&program).with_decode_cache();
+        b.iter(|| {
+            machine.reset(&program);
+            black_box(machine.run().unwrap())
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, day2_search, day7_chain, long_loop);
+criterion_main!(benches);
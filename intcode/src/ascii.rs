@@ -0,0 +1,194 @@
+use crate::machine::Machine;
+use crate::Outcome;
+use std::collections::{BTreeMap, VecDeque};
+
+pub const TERMINAL_HELP: &str = "\
+lines are sent to the program followed by a newline, except for:
+  :macro <name> <line>[; <line>...]   send the lines whenever <name> is entered
+  :macros                             list macros
+  :help                               show this help
+  :quit                               leave the terminal";
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AsciiOutput {
+    pub lines: Vec<String>,
+    pub values: Vec<i64>,
+}
+
+pub fn encode(text: &str) -> impl Iterator<Item = i64> + '_ {
+    text.bytes().map(i64::from)
+}
+
+pub fn push_text(input: &mut VecDeque<i64>, text: &str) {
+    input.extend(encode(text))
+}
+
+// Values outside the ASCII range are collected as numbers, a trailing line
+// without a newline is kept as well
+pub fn decode(output: &[i64]) -> AsciiOutput {
+    let mut decoded = AsciiOutput::default();
+    let mut line = String::new();
+
+    for &value in output {
+        match value {
+            10 => decoded.lines.push(std::mem::take(&mut line)),
+            0..=127 => line.push(value as u8 as char),
+            _ => decoded.values.push(value),
+        }
+    }
+    if !line.is_empty() {
+        decoded.lines.push(line);
+    }
+
+    decoded
+}
+
+pub struct Terminal {
+    machine: Machine,
+    macros: BTreeMap<String, Vec<String>>,
+    outcome: Option<Outcome>,
+}
+
+impl Terminal {
+    pub fn new(machine: Machine) -> Terminal {
+        Terminal {
+            machine,
+            macros: BTreeMap::new(),
+            outcome: None,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.outcome == Some(Outcome::Halted)
+    }
+
+    pub fn define_macro(&mut self, name: &str, lines: Vec<String>) {
+        self.macros.insert(name.to_string(), lines);
+    }
+
+    // Runs until the program asks for input and returns everything it printed
+    pub fn run(&mut self) -> Result<String, String> {
+        if self.is_halted() {
+            return Err("the program has halted".to_string());
+        }
+
+        let outcome = self.machine.run().map_err(|error| error.to_string())?;
+        self.outcome = Some(outcome);
+        let output: Vec<i64> = self.machine.output_mut().drain(..).collect();
+        let decoded = decode(&output);
+
+        Ok(decoded
+            .lines
+            .into_iter()
+            .chain(decoded.values.iter().map(|value| format!("[{}]", value)))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words.as_slice() {
+            [":help"] => Ok(TERMINAL_HELP.to_string()),
+            [":macros"] => Ok(self
+                .macros
+                .iter()
+                .map(|(name, lines)| format!("{}: {}", name, lines.join("; ")))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            [":macro", name, ..] => {
+                let body = line.trim_start()[":macro".len()..]
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .map(|(_, body)| body)
+                    .ok_or_else(|| format!("macro {} needs at least one line", name))?;
+                let lines = body
+                    .split(';')
+                    .map(|line| line.trim().to_string())
+                    .collect();
+                self.define_macro(name, lines);
+                Ok(String::new())
+            }
+            [command, ..] if command.starts_with(':') => {
+                Err(format!("unknown command {}, try :help", command))
+            }
+            _ => {
+                let lines = match self.macros.get(line.trim()) {
+                    Some(lines) => lines.clone(),
+                    None => vec![line.to_string()],
+                };
+                for line in lines {
+                    self.machine.push_text(&line);
+                    self.machine.push_input(10);
+                }
+                self.run()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    // Echoes its input with lower case letters turned to upper case
+    fn shout() -> Vec<i64> {
+        assemble(
+            "
+            loop:   in [c]
+                    lt [c], #97, [flag]
+                    jnz [flag], #print
+                    add [c], #-32, [c]
+            print:  out [c]
+                    jz #0, #loop
+            c:      data 0
+            flag:   data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let mut input = VecDeque::new();
+        push_text(&mut input, "Hi\n");
+        assert_eq!(input, vec![72, 105, 10]);
+
+        assert_eq!(
+            decode(&[72, 105, 10, 1234, 111, 107]),
+            AsciiOutput {
+                lines: vec!["Hi".to_string(), "ok".to_string()],
+                values: vec![1234],
+            }
+        );
+    }
+
+    #[test]
+    fn test_terminal_and_macros() {
+        let mut terminal = Terminal::new(Machine::new(&shout()));
+        assert_eq!(terminal.run(), Ok(String::new()));
+        assert_eq!(terminal.execute("hello"), Ok("HELLO".to_string()));
+
+        terminal.execute(":macro greet hi there; bye").unwrap();
+        assert_eq!(
+            terminal.execute(":macros"),
+            Ok("greet: hi there; bye".to_string())
+        );
+        assert_eq!(terminal.execute("greet"), Ok("HI THERE\nBYE".to_string()));
+        assert!(terminal.execute(":nope").is_err());
+        assert!(!terminal.is_halted());
+    }
+
+    #[test]
+    fn test_numeric_results() {
+        let mut terminal = Terminal::new(Machine::new(&[104, 79, 104, 75, 104, 4242, 99]));
+        assert_eq!(terminal.run(), Ok("OK\n[4242]".to_string()));
+        assert!(terminal.is_halted());
+        assert!(terminal.run().is_err());
+    }
+}
@@ -0,0 +1,506 @@
+use crate::registry::{self, Registry};
+use crate::word::Word;
+use crate::Memory;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AssembleErrorKind,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AssembleErrorKind {
+    UnknownMnemonic(String),
+    OperandCount { expected: usize, found: usize },
+    InvalidOperand(String),
+    InvalidValue(String),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+}
+
+impl fmt::Display for AssembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleErrorKind::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown mnemonic `{}`", mnemonic)
+            }
+            AssembleErrorKind::OperandCount { expected, found } => {
+                write!(f, "expected {} operands, found {}", expected, found)
+            }
+            AssembleErrorKind::InvalidOperand(operand) => {
+                write!(f, "invalid operand `{}`", operand)
+            }
+            AssembleErrorKind::InvalidValue(value) => write!(f, "invalid value `{}`", value),
+            AssembleErrorKind::UndefinedLabel(label) => write!(f, "undefined label `{}`", label),
+            AssembleErrorKind::DuplicateLabel(label) => write!(f, "duplicate label `{}`", label),
+        }
+    }
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl Error for AssembleError {}
+
+type AssembleResult<T> = Result<T, AssembleError>;
+
+enum Value {
+    Number(i64),
+    Label {
+        name: String,
+        negated: bool,
+        line: usize,
+        column: usize,
+    },
+}
+
+// A part of a line together with its 1-based column
+#[derive(Clone, Copy)]
+struct Span<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+impl<'a> Span<'a> {
+    fn trim(self) -> Span<'a> {
+        let trimmed_start = self.text.trim_start();
+        Span {
+            text: trimmed_start.trim_end(),
+            column: self.column + self.text.len() - trimmed_start.len(),
+        }
+    }
+
+    fn split_at(self, mid: usize) -> (Span<'a>, Span<'a>) {
+        let (head, tail) = self.text.split_at(mid);
+        (
+            Span {
+                text: head,
+                column: self.column,
+            },
+            Span {
+                text: tail,
+                column: self.column + mid,
+            },
+        )
+    }
+
+    fn split_commas(self) -> Vec<Span<'a>> {
+        let mut spans = vec![];
+        let mut rest = self;
+        while let Some(i) = rest.text.find(',') {
+            let (head, tail) = rest.split_at(i);
+            spans.push(head.trim());
+            rest = tail.split_at(1).1;
+        }
+        spans.push(rest.trim());
+        spans
+    }
+}
+
+// The opcode and parameter count of a mnemonic
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<(usize, usize)>;
+
+struct Assembler<'a> {
+    words: Vec<Value>,
+    labels: HashMap<String, usize>,
+    find_opcode: Lookup<'a>,
+}
+
+pub fn assemble(source: &str) -> Result<Memory, AssembleError> {
+    assemble_by(source, &|mnemonic| {
+        registry::BUILTINS
+            .iter()
+            .find(|builtin| builtin.mnemonic == mnemonic)
+            .map(|builtin| (builtin.opcode, builtin.roles.len()))
+    })
+}
+
+// Assembles the instructions of `registry`, including the ones registered
+// on top of the built-in set
+pub fn assemble_with<W: Word>(
+    source: &str,
+    registry: &Registry<W>,
+) -> Result<Memory, AssembleError> {
+    assemble_by(source, &|mnemonic| {
+        registry
+            .find(mnemonic)
+            .map(|(opcode, instruction)| (opcode, instruction.roles().len()))
+    })
+}
+
+fn assemble_by(source: &str, find_opcode: Lookup) -> Result<Memory, AssembleError> {
+    let mut assembler = Assembler {
+        words: vec![],
+        labels: HashMap::new(),
+        find_opcode,
+    };
+
+    for (i, line) in source.lines().enumerate() {
+        assembler.parse_line(i + 1, line)?;
+    }
+
+    assembler.resolve()
+}
+
+impl Assembler<'_> {
+    fn parse_line(&mut self, line: usize, text: &str) -> AssembleResult<()> {
+        let without_comment = text.split(';').next().unwrap_or("");
+        let mut rest = Span {
+            text: without_comment,
+            column: 1,
+        }
+        .trim();
+
+        while let Some(i) = rest.text.find(':') {
+            let (label, tail) = rest.split_at(i);
+            let label = label.trim();
+            rest = tail.split_at(1).1.trim();
+
+            // Addresses in front of disassembled lines are only informational
+            if label.text.chars().all(|c| c.is_ascii_digit()) {
+                continue;
+            }
+            if !is_identifier(label.text) {
+                return Err(error_at(
+                    line,
+                    label,
+                    AssembleErrorKind::InvalidValue(label.text.to_string()),
+                ));
+            }
+            if self
+                .labels
+                .insert(label.text.to_string(), self.words.len())
+                .is_some()
+            {
+                return Err(error_at(
+                    line,
+                    label,
+                    AssembleErrorKind::DuplicateLabel(label.text.to_string()),
+                ));
+            }
+        }
+
+        if rest.text.is_empty() {
+            return Ok(());
+        }
+
+        let mnemonic_end = rest
+            .text
+            .find(char::is_whitespace)
+            .unwrap_or(rest.text.len());
+        let (mnemonic, operands) = rest.split_at(mnemonic_end);
+        let operands = match operands.trim().text {
+            "" => vec![],
+            _ => operands.trim().split_commas(),
+        };
+
+        if mnemonic.text == "data" {
+            for operand in operands {
+                let value = parse_value(line, operand, false)?;
+                self.words.push(value);
+            }
+            return Ok(());
+        }
+
+        let (opcode, arity) = (self.find_opcode)(mnemonic.text).ok_or_else(|| {
+            error_at(
+                line,
+                mnemonic,
+                AssembleErrorKind::UnknownMnemonic(mnemonic.text.to_string()),
+            )
+        })?;
+        if operands.len() != arity {
+            return Err(error_at(
+                line,
+                mnemonic,
+                AssembleErrorKind::OperandCount {
+                    expected: arity,
+                    found: operands.len(),
+                },
+            ));
+        }
+
+        let mut instruction = opcode as i64;
+        let mut values = vec![];
+        for (i, operand) in operands.into_iter().enumerate() {
+            let (mode, value) = parse_operand(line, operand)?;
+            instruction += mode * 10_i64.pow(2 + i as u32);
+            values.push(value);
+        }
+
+        self.words.push(Value::Number(instruction));
+        self.words.extend(values);
+
+        Ok(())
+    }
+
+    fn resolve(self) -> Result<Memory, AssembleError> {
+        let labels = self.labels;
+        self.words
+            .into_iter()
+            .map(|word| match word {
+                Value::Number(value) => Ok(value),
+                Value::Label {
+                    name,
+                    negated,
+                    line,
+                    column,
+                } => match labels.get(&name) {
+                    Some(&address) if negated => Ok(-(address as i64)),
+                    Some(&address) => Ok(address as i64),
+                    None => Err(AssembleError {
+                        line,
+                        column,
+                        kind: AssembleErrorKind::UndefinedLabel(name),
+                    }),
+                },
+            })
+            .collect()
+    }
+}
+
+fn parse_operand(line: usize, operand: Span) -> AssembleResult<(i64, Value)> {
+    let invalid = || {
+        error_at(
+            line,
+            operand,
+            AssembleErrorKind::InvalidOperand(operand.text.to_string()),
+        )
+    };
+
+    if operand.text.starts_with('#') {
+        return Ok((1, parse_value(line, operand.split_at(1).1.trim(), false)?));
+    }
+    if !operand.text.starts_with('[') || !operand.text.ends_with(']') || operand.text.len() < 2 {
+        return Err(invalid());
+    }
+    let inner = operand.split_at(1).1;
+    let inner = inner.split_at(inner.text.len() - 1).0.trim();
+
+    // Labels like `rbuf` start with `rb` as well, only `rb` on its own or
+    // followed by an offset is the relative base
+    if inner.text.starts_with("rb") {
+        let (_, offset) = inner.split_at(2);
+        let offset = offset.trim();
+        match offset.text.chars().next() {
+            None => return Ok((2, Value::Number(0))),
+            Some(sign @ '+') | Some(sign @ '-') => {
+                let value = parse_value(line, offset.split_at(1).1.trim(), sign == '-')?;
+                return Ok((2, value));
+            }
+            _ => {}
+        }
+    }
+
+    Ok((0, parse_value(line, inner, false)?))
+}
+
+fn parse_value(line: usize, value: Span, negated: bool) -> AssembleResult<Value> {
+    if is_identifier(value.text) {
+        return Ok(Value::Label {
+            name: value.text.to_string(),
+            negated,
+            line,
+            column: value.column,
+        });
+    }
+
+    let invalid = || {
+        error_at(
+            line,
+            value,
+            AssembleErrorKind::InvalidValue(value.text.to_string()),
+        )
+    };
+    // Parsed wider, so the offset of `[rb-9223372036854775808]` fits once negated
+    let number: i128 = value.text.parse().map_err(|_| invalid())?;
+    let number = if negated { -number } else { number };
+    Ok(Value::Number(number.try_into().map_err(|_| invalid())?))
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn error_at(line: usize, span: Span, kind: AssembleErrorKind) -> AssembleError {
+    AssembleError {
+        line,
+        column: span.column,
+        kind,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::listing;
+
+    #[test]
+    fn test_assemble_website() {
+        assert_eq!(
+            assemble(
+                "add [9], [10], [3]\n\
+                 mul [3], [11], [0]\n\
+                 hlt\n\
+                 data 30, 40, 50\n"
+            ),
+            Ok(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50])
+        );
+    }
+
+    #[test]
+    fn test_assemble_labels_and_modes() {
+        let source = "
+            ; echoes the input relative to a moved base
+            start:  arb #tail
+                    in [rb-1]
+                    out [rb - 1]
+                    jz #0, #end
+            end:    hlt
+            base:   data 0
+            tail:   data end
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![109, 11, 203, -1, 204, -1, 1106, 0, 9, 99, 0, 9])
+        );
+    }
+
+    #[test]
+    fn test_assemble_day5_big() {
+        let source = "
+                  in [input]
+                  eq [input], #8, [flag]
+                  jnz [flag], #equal
+                  lt #8, [input], [flag]
+                  jz [flag], #lower
+                  jz #0, #greater
+                  data 98
+            flag: data 0
+            input: data 0
+            equal: mul [input], #125, [flag]
+                  out [flag]
+                  jnz #1, #done
+            lower: out #999
+                  jnz #1, #done
+            greater: add #1000, #1, [flag]
+                  out [flag]
+                  jnz #1, #done
+                  data 98
+            done: hlt
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ])
+        );
+    }
+
+    #[test]
+    fn test_round_trip_through_disassembler() {
+        let quine = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(assemble(&listing(&quine)), Ok(quine));
+
+        let code = assemble("l: in [rb+3]\nadd #-2, [l], [rb]\ndata 7, 10099, -5\n").unwrap();
+        assert_eq!(assemble(&listing(&code)), Ok(code));
+
+        let code = vec![204, i64::MIN, 109, i64::MAX];
+        assert_eq!(assemble(&listing(&code)), Ok(code));
+    }
+
+    #[test]
+    fn test_labels_starting_with_rb() {
+        assert_eq!(
+            assemble("out [rbuf]\nout [rb + rbuf]\nhlt\nrbuf: data 42"),
+            Ok(vec![4, 5, 204, 5, 99, 42])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble("hlt\n  foo [1]"),
+            Err(AssembleError {
+                line: 2,
+                column: 3,
+                kind: AssembleErrorKind::UnknownMnemonic("foo".to_string()),
+            })
+        );
+        assert_eq!(
+            assemble("add [1], [2]"),
+            Err(AssembleError {
+                line: 1,
+                column: 1,
+                kind: AssembleErrorKind::OperandCount {
+                    expected: 3,
+                    found: 2
+                },
+            })
+        );
+        assert_eq!(
+            assemble("out  1"),
+            Err(AssembleError {
+                line: 1,
+                column: 6,
+                kind: AssembleErrorKind::InvalidOperand("1".to_string()),
+            })
+        );
+        assert_eq!(
+            assemble("out #1x"),
+            Err(AssembleError {
+                line: 1,
+                column: 6,
+                kind: AssembleErrorKind::InvalidValue("1x".to_string()),
+            })
+        );
+        assert_eq!(
+            assemble("\njz #0, #nowhere"),
+            Err(AssembleError {
+                line: 2,
+                column: 9,
+                kind: AssembleErrorKind::UndefinedLabel("nowhere".to_string()),
+            })
+        );
+        assert_eq!(
+            assemble("a: hlt\na: hlt"),
+            Err(AssembleError {
+                line: 2,
+                column: 1,
+                kind: AssembleErrorKind::DuplicateLabel("a".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_error_display() {
+        assert_eq!(
+            assemble("out [rb*2]").unwrap_err().to_string(),
+            "line 1, column 6: invalid value `rb*2`"
+        );
+    }
+}
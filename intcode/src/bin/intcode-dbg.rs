@@ -0,0 +1,43 @@
+use intcode::debugger::{Debugger, HELP};
+use intcode::{input, IntcodeState};
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let file = match args.as_slice() {
+        [file] => file,
+        _ => {
+            eprintln!("usage: intcode-dbg <file>");
+            process::exit(1);
+        }
+    };
+
+    let mut debugger = Debugger::new(IntcodeState::from(input::get_memory_from_file(file)));
+    println!("{}\n=> {}", HELP, debugger.current_instruction());
+
+    let stdin = io::stdin();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().expect("could not write to stdout");
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("could not read from stdin")
+            == 0
+        {
+            break;
+        }
+        match line.trim() {
+            "q" | "quit" => break,
+            command => match debugger.execute(command) {
+                Ok(message) if message.is_empty() => {}
+                Ok(message) => println!("{}", message),
+                Err(message) => eprintln!("error: {}", message),
+            },
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::io::IntcodeIo;
+use crate::{decode, execute_decoded, IntcodeError, IntcodeState, Outcome, ProgramState};
+
+// Decoded instructions by address. A write to an address drops its entry, so
+// self-modifying programs are decoded again before the changed word runs
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct DecodeCache {
+    entries: Vec<Option<ProgramState>>,
+}
+
+impl DecodeCache {
+    pub fn new() -> DecodeCache {
+        DecodeCache::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn invalidate(&mut self, address: usize) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = None;
+        }
+    }
+
+    fn decode(&mut self, intcode_state: &IntcodeState) -> Result<ProgramState, IntcodeError> {
+        let index = intcode_state.index;
+        if let Some(Some(program_state)) = self.entries.get(index) {
+            return Ok(*program_state);
+        }
+
+        let program_state = decode(intcode_state)?;
+        if index >= self.entries.len() {
+            self.entries.resize(index + 1, None);
+        }
+        self.entries[index] = Some(program_state);
+
+        Ok(program_state)
+    }
+}
+
+pub(crate) fn execute_step_cached(
+    intcode_state: &mut IntcodeState,
+    io: &mut dyn IntcodeIo,
+    cache: &mut DecodeCache,
+) -> Result<Outcome, IntcodeError> {
+    if intcode_state.step_budget == Some(0) {
+        return Ok(Outcome::BudgetExhausted);
+    }
+
+    let program_state = cache.decode(intcode_state)?;
+    let (outcome, write) = execute_decoded(intcode_state, program_state, io)?;
+    if let Some(address) = write {
+        cache.invalidate(address);
+    }
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::get_memory_from_file;
+    use crate::machine::Machine;
+    use crate::test_programs::adder;
+    use crate::{run_instruction_set_with_inputs, IntcodeReturnType, Memory};
+
+    fn assert_same_as_reference(code: Memory, inputs: Vec<i64>) {
+        let mut machine = Machine::new(&code).with_decode_cache();
+        for &input in &inputs {
+            machine.push_input(input);
+        }
+        let cached = machine.run().map(|outcome| (outcome, machine.into_state()));
+
+        let reference =
+            run_instruction_set_with_inputs(code, inputs).map(|return_type| match return_type {
+                IntcodeReturnType::Finished(state) => (Outcome::Halted, state),
+                IntcodeReturnType::Interrupted(state) => (Outcome::WaitingForInput, state),
+                IntcodeReturnType::BudgetExhausted(state) => (Outcome::BudgetExhausted, state),
+            });
+        assert_eq!(cached, reference);
+    }
+
+    #[test]
+    fn test_example_programs() {
+        let programs = vec![
+            (vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], vec![]),
+            (vec![1, 1, 1, 4, 99, 5, 6, 0, 99], vec![]),
+            (vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], vec![8]),
+            (vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], vec![5]),
+            (
+                vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+                vec![0],
+            ),
+            (
+                vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+                vec![7],
+            ),
+            (vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0], vec![]),
+            (
+                vec![
+                    109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+                ],
+                vec![],
+            ),
+            (vec![109, 10, 203, -1, 204, -1, 99, 0, 0, 0], vec![42]),
+            (adder(), vec![3]),
+            (vec![1, 0, 0, 0, 1, -34, 4, 5], vec![]),
+            (vec![1101, -100, 1, 4, 0], vec![]),
+        ];
+
+        for (code, inputs) in programs {
+            assert_same_as_reference(code, inputs);
+        }
+    }
+
+    #[test]
+    fn test_puzzle_inputs() {
+        let day5 = get_memory_from_file("../day5/resources/input");
+        assert_same_as_reference(day5.clone(), vec![1]);
+        assert_same_as_reference(day5, vec![5]);
+
+        let day7 = get_memory_from_file("../day7/resources/input");
+        for phase in 0..10 {
+            assert_same_as_reference(day7.clone(), vec![phase, 17]);
+        }
+    }
+
+    #[test]
+    fn test_self_modifying_loop() {
+        // Doubles [21] on the first pass, then rewrites the instruction at 4
+        // into an addition of [2] for the remaining passes
+        let code = vec![
+            1001, 20, 1, 20, 1002, 21, 2, 21, 1101, 1, 0, 4, 1007, 20, 3, 22, 1005, 22, 0, 99, 0,
+            5, 0,
+        ];
+        let mut machine = Machine::new(&code).with_decode_cache();
+        assert_eq!(machine.run(), Ok(Outcome::Halted));
+        assert_eq!(machine.memory()[21], 12);
+
+        assert_same_as_reference(code, vec![]);
+    }
+}
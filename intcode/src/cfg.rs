@@ -0,0 +1,277 @@
+use crate::disassembler::{statement_at, Operand, Statement};
+use crate::ProgramState::{Halted, Running};
+use crate::{Memory, OpMode, ProgramState};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EdgeKind {
+    Jump,
+    Fallthrough,
+    // The target of the jump is read from memory at runtime
+    Unknown,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Edge {
+    pub from: usize,
+    pub to: Option<usize>,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub statements: Vec<Statement>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+// The successors of a single statement. `None` marks a jump whose target is
+// unknown, the flag tells whether the statement ends its block
+fn successors(code: &Memory, statement: &Statement) -> (Vec<(Option<usize>, EdgeKind)>, bool) {
+    let next = statement.address() + statement.size();
+    let operands = match statement {
+        Statement::Instruction { operands, .. } => operands,
+        // The machine fails on words that do not decode
+        Statement::Data { .. } => return (vec![], true),
+    };
+
+    match ProgramState::from_memory_location(code[statement.address()]) {
+        Ok(Running(op_mode @ (OpMode::JumpIfTrue(..) | OpMode::JumpIfFalse(..)))) => {
+            let jumps_on_zero = matches!(op_mode, OpMode::JumpIfFalse(..));
+            let mut successors = vec![];
+
+            let always_jumps = match operands[0] {
+                Operand::Immediate(condition) => (condition == 0) == jumps_on_zero,
+                _ => false,
+            };
+            let never_jumps = match operands[0] {
+                Operand::Immediate(_) => !always_jumps,
+                _ => false,
+            };
+            if !never_jumps {
+                successors.push(match operands[1] {
+                    Operand::Immediate(target) if target >= 0 => {
+                        (Some(target as usize), EdgeKind::Jump)
+                    }
+                    _ => (None, EdgeKind::Unknown),
+                });
+            }
+            if !always_jumps {
+                successors.push((Some(next), EdgeKind::Fallthrough));
+            }
+
+            (successors, true)
+        }
+        Ok(Halted) => (vec![], true),
+        _ => (vec![(Some(next), EdgeKind::Fallthrough)], false),
+    }
+}
+
+// Follows every statically known path from address 0. Code that is only
+// written at runtime is analysed as it is stored in `code`
+pub fn extract(code: &Memory) -> ControlFlowGraph {
+    extract_from(code, &[0])
+}
+
+// Targets of computed jumps can be passed as additional entry points
+pub fn extract_from(code: &Memory, entries: &[usize]) -> ControlFlowGraph {
+    let mut reachable = BTreeMap::new();
+    let mut leaders: BTreeSet<usize> = entries.iter().copied().collect();
+    let mut pending = entries.to_vec();
+
+    while let Some(address) = pending.pop() {
+        if reachable.contains_key(&address) {
+            continue;
+        }
+        let statement = statement_at(code, address);
+        let (next, ends_block) = successors(code, &statement);
+        for (target, _) in next {
+            if let Some(target) = target {
+                if ends_block {
+                    leaders.insert(target);
+                }
+                pending.push(target);
+            }
+        }
+        reachable.insert(address, statement);
+    }
+
+    let mut graph = ControlFlowGraph {
+        blocks: BTreeMap::new(),
+        edges: vec![],
+    };
+    for &start in leaders.iter().filter(|start| reachable.contains_key(start)) {
+        let mut block = BasicBlock {
+            start,
+            statements: vec![],
+        };
+        let mut address = start;
+        loop {
+            let statement = reachable[&address].clone();
+            let (next, ends_block) = successors(code, &statement);
+            address += statement.size();
+            block.statements.push(statement);
+
+            if ends_block || leaders.contains(&address) {
+                graph.edges.extend(next.into_iter().map(|(to, kind)| Edge {
+                    from: start,
+                    to,
+                    kind,
+                }));
+                break;
+            }
+        }
+        graph.blocks.insert(start, block);
+    }
+
+    graph
+}
+
+impl ControlFlowGraph {
+    // Edges that lead back to the start of a block at or before their own,
+    // every loop has at least one of them
+    pub fn back_edges(&self) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| matches!(edge.to, Some(to) if to <= edge.from))
+            .collect()
+    }
+
+    pub fn unknown_edges(&self) -> Vec<&Edge> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Unknown)
+            .collect()
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot =
+            String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+
+        for block in self.blocks.values() {
+            let label: String = block
+                .statements
+                .iter()
+                .map(|statement| format!("{}\\l", statement.to_string().trim_start()))
+                .collect();
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+        for edge in &self.edges {
+            match (edge.to, edge.kind) {
+                (Some(to), EdgeKind::Jump) => writeln!(dot, "    b{} -> b{};", edge.from, to),
+                (Some(to), _) => writeln!(dot, "    b{} -> b{} [style=dashed];", edge.from, to),
+                (None, _) => writeln!(
+                    dot,
+                    "    unknown{0} [label=\"?\", shape=diamond];\n    b{0} -> unknown{0} [color=red];",
+                    edge.from
+                ),
+            }
+            .unwrap();
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::get_memory_from_file;
+    use crate::test_programs::countdown;
+
+    #[test]
+    fn test_blocks_and_edges() {
+        let graph = extract(&countdown());
+
+        assert_eq!(
+            graph.blocks.keys().copied().collect::<Vec<_>>(),
+            vec![0, 2, 11]
+        );
+        assert_eq!(graph.blocks[&2].statements.len(), 3);
+        assert_eq!(
+            graph.edges,
+            vec![
+                Edge {
+                    from: 0,
+                    to: Some(2),
+                    kind: EdgeKind::Fallthrough
+                },
+                Edge {
+                    from: 2,
+                    to: Some(2),
+                    kind: EdgeKind::Jump
+                },
+                Edge {
+                    from: 2,
+                    to: Some(11),
+                    kind: EdgeKind::Fallthrough
+                },
+            ]
+        );
+        assert_eq!(graph.back_edges().len(), 1);
+    }
+
+    #[test]
+    fn test_constant_conditions_and_computed_jumps() {
+        // jz #0, #6 always jumps, jnz [0], [7] jumps to an address from memory
+        let graph = extract(&vec![1106, 0, 6, 104, 1, 99, 5, 0, 7]);
+
+        assert_eq!(
+            graph.blocks.keys().copied().collect::<Vec<_>>(),
+            vec![0, 6, 9]
+        );
+        assert_eq!(graph.unknown_edges().len(), 1);
+        assert_eq!(
+            graph.edges[0],
+            Edge {
+                from: 0,
+                to: Some(6),
+                kind: EdgeKind::Jump
+            }
+        );
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b0 [label=\"0: jz #0, #6\\l\"];\n"));
+        assert!(dot.contains("    b0 -> b6;\n"));
+        assert!(dot.contains("    b6 -> unknown6 [color=red];\n"));
+    }
+
+    #[test]
+    fn test_puzzle_programs() {
+        // The diagnostic patches the instruction at 6 with its input first
+        let mut diagnostic = get_memory_from_file("../day5/resources/input");
+        let unpatched = extract(&diagnostic);
+        assert_eq!(unpatched.blocks.len(), 1);
+        assert_eq!(unpatched.blocks[&0].statements.len(), 3);
+
+        diagnostic[6] += 5;
+        let graph = extract(&diagnostic);
+        assert_eq!(graph.blocks.len(), 14);
+        assert_eq!(graph.unknown_edges().len(), 1);
+        assert!(graph.back_edges().is_empty());
+
+        // The firmware picks its routine with a computed jump through a table
+        let firmware = get_memory_from_file("../day7/resources/input");
+        assert_eq!(extract(&firmware).unknown_edges().len(), 1);
+
+        let mut entries = vec![0];
+        entries.extend(firmware[10..20].iter().map(|&address| address as usize));
+        let graph = extract_from(&firmware, &entries);
+        assert_eq!(graph.blocks.len(), 11);
+        assert!(graph.back_edges().is_empty());
+        assert!(graph.blocks.values().skip(1).all(|block| block
+            .statements
+            .last()
+            .unwrap()
+            .to_string()
+            .ends_with("hlt")));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::IntcodeState;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Brent's algorithm: every state is compared against a single checkpoint that
+// moves forward whenever the distance to it reaches the next power of two
+pub struct CycleDetector {
+    checkpoint: Option<(u64, IntcodeState)>,
+    distance: u64,
+    power: u64,
+}
+
+impl CycleDetector {
+    pub fn new() -> CycleDetector {
+        CycleDetector {
+            checkpoint: None,
+            distance: 0,
+            power: 1,
+        }
+    }
+
+    pub fn observe(&mut self, intcode_state: &IntcodeState) -> Option<u64> {
+        let fingerprint = fingerprint(intcode_state);
+
+        match &self.checkpoint {
+            None => {
+                self.checkpoint = Some((fingerprint, intcode_state.clone()));
+                return None;
+            }
+            Some((checkpoint_fingerprint, checkpoint)) => {
+                self.distance += 1;
+                if *checkpoint_fingerprint == fingerprint && same_machine(checkpoint, intcode_state)
+                {
+                    return Some(self.distance);
+                }
+            }
+        }
+
+        if self.distance == self.power {
+            self.checkpoint = Some((fingerprint, intcode_state.clone()));
+            self.power *= 2;
+            self.distance = 0;
+        }
+
+        None
+    }
+}
+
+impl Default for CycleDetector {
+    fn default() -> Self {
+        CycleDetector::new()
+    }
+}
+
+// The output and the step budget do not influence the following steps, so
+// they are left out when comparing machines
+fn fingerprint(intcode_state: &IntcodeState) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    intcode_state.code.hash(&mut hasher);
+    intcode_state.index.hash(&mut hasher);
+    intcode_state.relative_base.hash(&mut hasher);
+    intcode_state.input.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn same_machine(a: &IntcodeState, b: &IntcodeState) -> bool {
+    a.code == b.code
+        && a.index == b.index
+        && a.relative_base == b.relative_base
+        && a.input == b.input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{run_detecting_cycles, IntcodeError, IntcodeErrorKind, IntcodeReturnType};
+
+    #[test]
+    fn test_detects_jump_to_self() {
+        assert_eq!(
+            run_detecting_cycles(IntcodeState::from(vec![1105, 1, 0])),
+            Err(IntcodeError {
+                pc: 0,
+                instruction: 1105,
+                parameter: None,
+                kind: IntcodeErrorKind::InfiniteLoop { cycle_length: 1 }
+            })
+        );
+    }
+
+    #[test]
+    fn test_detects_loop_after_warm_up() {
+        // Counts down from 5 and then keeps outputting the counter forever
+        let code = vec![1001, 13, -1, 13, 1005, 13, 0, 4, 13, 1105, 1, 7, 99, 5];
+        match run_detecting_cycles(IntcodeState::from(code)) {
+            Err(error) => {
+                assert_eq!(
+                    error.kind,
+                    IntcodeErrorKind::InfiniteLoop { cycle_length: 2 }
+                )
+            }
+            result => panic!("wrong enum variant {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_terminating_program() {
+        // Counts down from 5 and halts
+        let code = vec![1001, 8, -1, 8, 1005, 8, 0, 99, 5];
+        assert!(matches!(
+            run_detecting_cycles(IntcodeState::from(code)),
+            Ok(IntcodeReturnType::Finished(_))
+        ));
+    }
+}
@@ -0,0 +1,544 @@
+use crate::disassembler::{self, Statement};
+use crate::history::History;
+use crate::registry;
+use crate::{IntcodeError, IntcodeErrorKind, IntcodeReturnType, IntcodeState, Stop};
+use std::collections::BTreeSet;
+use std::fs;
+
+pub const HELP: &str = "\
+commands:
+  s, step [n]             execute n instructions (default 1)
+  c, continue             run until a breakpoint, halt, missing input or error
+  bs, back [n]            undo the last n instructions (default 1)
+  bw, back-write <addr>   undo instructions up to the last write to <addr>
+  b, break <addr>         break when the program counter reaches <addr>
+  bo, break-op <op>       break before instructions with opcode or mnemonic <op>
+  d, delete <addr|op>     remove a breakpoint
+  bl, breakpoints         list breakpoints
+  x <addr> [n]            show n memory cells starting at <addr> (default 1)
+  poke <addr> <value>     write <value> to memory at <addr>
+  l, list [n]             disassemble n instructions from the program counter (default 5)
+  in <values...>          append values to the input queue
+  save <file>             write a snapshot of the machine to <file>
+  load <file>             restore the machine from a snapshot in <file>
+  i, info                 show registers, input and output
+  h, help                 show this help
+  q, quit                 leave the debugger";
+
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Ready,
+    WaitingForInput,
+    BudgetExhausted,
+    Halted,
+    Failed(IntcodeError),
+}
+
+pub struct Debugger {
+    state: IntcodeState,
+    status: Status,
+    history: History,
+    address_breakpoints: BTreeSet<usize>,
+    opcode_breakpoints: BTreeSet<i64>,
+}
+
+impl Debugger {
+    pub fn new(state: IntcodeState) -> Debugger {
+        Debugger {
+            state,
+            status: Status::Ready,
+            history: History::new(),
+            address_breakpoints: BTreeSet::new(),
+            opcode_breakpoints: BTreeSet::new(),
+        }
+    }
+
+    pub fn state(&self) -> &IntcodeState {
+        &self.state
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    pub fn current_instruction(&self) -> Statement {
+        disassembler::statement_at(&self.state.code, self.state.index)
+    }
+
+    pub fn step(&mut self) -> &Status {
+        if let Status::Halted | Status::Failed(_) = self.status {
+            return &self.status;
+        }
+
+        self.status = match self.history.step(self.state.clone()) {
+            Ok(state) => {
+                self.state = state;
+                Status::Ready
+            }
+            Err(Stop::Returned(IntcodeReturnType::Finished(state))) => {
+                self.state = state;
+                Status::Halted
+            }
+            Err(Stop::Returned(IntcodeReturnType::Interrupted(state))) => {
+                self.state = state;
+                Status::WaitingForInput
+            }
+            Err(Stop::Returned(IntcodeReturnType::BudgetExhausted(state))) => {
+                self.state = state;
+                Status::BudgetExhausted
+            }
+            Err(Stop::Failed(error)) => Status::Failed(error),
+        };
+
+        &self.status
+    }
+
+    pub fn continue_execution(&mut self) -> &Status {
+        while self.step() == &Status::Ready && !self.at_breakpoint() {}
+
+        &self.status
+    }
+
+    pub fn at_breakpoint(&self) -> bool {
+        let opcode = self.state.code.get(self.state.index).map(|word| word % 100);
+
+        self.address_breakpoints.contains(&self.state.index)
+            || opcode.is_some_and(|opcode| self.opcode_breakpoints.contains(&opcode))
+    }
+
+    pub fn restore(&mut self, state: IntcodeState) {
+        self.state = state;
+        self.status = Status::Ready;
+        self.history = History::new();
+    }
+
+    pub fn step_back(&mut self, steps: usize) -> usize {
+        let steps = steps.min(self.history.len());
+        let state = std::mem::take(&mut self.state);
+        self.state = self.history.rewind(state, steps);
+        if steps > 0 {
+            self.status = Status::Ready;
+        }
+
+        steps
+    }
+
+    pub fn step_back_to_write(&mut self, address: usize) -> Option<usize> {
+        let steps = self.history.steps_since_write(address)?;
+
+        Some(self.step_back(steps))
+    }
+
+    pub fn push_input(&mut self, input: i64) {
+        self.state.input.push_back(input);
+        if self.status == Status::WaitingForInput {
+            self.status = Status::Ready;
+        }
+    }
+
+    pub fn poke(&mut self, address: usize, value: i64) -> Result<(), IntcodeErrorKind> {
+        if address >= self.state.memory_limit {
+            return Err(IntcodeErrorKind::MemoryLimitExceeded {
+                address,
+                limit: self.state.memory_limit,
+            });
+        }
+        if address >= self.state.code.len() {
+            self.state.code.resize(address + 1, 0);
+        }
+        self.state.code[address] = value;
+        // The patched word may be what the machine failed on
+        self.status = Status::Ready;
+
+        Ok(())
+    }
+
+    pub fn peek(&self, address: usize) -> i64 {
+        self.state.code.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn execute(&mut self, command: &str) -> Result<String, String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["s"] | ["step"] => {
+                self.step();
+                Ok(self.describe_position())
+            }
+            ["s", n] | ["step", n] => {
+                for _ in 0..parse_number::<usize>(n)? {
+                    if self.step() != &Status::Ready {
+                        break;
+                    }
+                }
+                Ok(self.describe_position())
+            }
+            ["c"] | ["continue"] => {
+                self.continue_execution();
+                Ok(self.describe_position())
+            }
+            ["bs"] | ["back"] => self.execute("back 1"),
+            ["bs", n] | ["back", n] => {
+                let steps = self.step_back(parse_number(n)?);
+                Ok(format!(
+                    "back {} steps\n{}",
+                    steps,
+                    self.describe_position()
+                ))
+            }
+            ["bw", address] | ["back-write", address] => {
+                let address = parse_number(address)?;
+                match self.step_back_to_write(address) {
+                    Some(steps) => Ok(format!(
+                        "back {} steps\n{}",
+                        steps,
+                        self.describe_position()
+                    )),
+                    None => Err(format!("no recorded write to {}", address)),
+                }
+            }
+            ["b", address] | ["break", address] => {
+                let address = parse_number(address)?;
+                self.address_breakpoints.insert(address);
+                Ok(format!("breakpoint at {}", address))
+            }
+            ["bo", op] | ["break-op", op] => {
+                let opcode = parse_opcode(op)?;
+                self.opcode_breakpoints.insert(opcode);
+                Ok(format!("breakpoint on opcode {}", opcode))
+            }
+            ["d", target] | ["delete", target] => {
+                let removed = match target.parse::<usize>() {
+                    Ok(address) => self.address_breakpoints.remove(&address),
+                    Err(_) => self.opcode_breakpoints.remove(&parse_opcode(target)?),
+                };
+                if removed {
+                    Ok(format!("deleted breakpoint {}", target))
+                } else {
+                    Err(format!("no breakpoint {}", target))
+                }
+            }
+            ["bl"] | ["breakpoints"] => Ok(format!(
+                "addresses: {:?}\nopcodes: {:?}",
+                self.address_breakpoints, self.opcode_breakpoints
+            )),
+            ["x", address] => self.execute(&format!("x {} 1", address)),
+            ["x", address, n] => {
+                let address: usize = parse_number(address)?;
+                let n: usize = parse_number(n)?;
+                let end = address.checked_add(n).ok_or_else(|| {
+                    format!("{} cells from {} exceed the address space", n, address)
+                })?;
+                if end > self.state.memory_limit {
+                    return Err(format!(
+                        "{} cells from {} exceed the memory limit of {}",
+                        n, address, self.state.memory_limit
+                    ));
+                }
+                Ok((address..end)
+                    .map(|a| format!("{:>5}: {}", a, self.peek(a)))
+                    .collect::<Vec<_>>()
+                    .join("\n"))
+            }
+            ["poke", address, value] => {
+                let address = parse_number(address)?;
+                let value = parse_number(value)?;
+                self.poke(address, value).map_err(|kind| kind.to_string())?;
+                Ok(format!("{:>5}: {}", address, value))
+            }
+            ["l"] | ["list"] => self.execute("list 5"),
+            ["l", n] | ["list", n] => {
+                let mut address = self.state.index;
+                let mut lines = vec![];
+                for _ in 0..parse_number::<usize>(n)? {
+                    let statement = disassembler::statement_at(&self.state.code, address);
+                    address += statement.size();
+                    lines.push(statement.to_string());
+                }
+                Ok(lines.join("\n"))
+            }
+            ["in", values @ ..] if !values.is_empty() => {
+                for value in values {
+                    self.push_input(parse_number(value)?);
+                }
+                Ok(format!("input: {:?}", self.state.input))
+            }
+            ["save", file] => {
+                let json = self.state.to_json().map_err(|error| error.to_string())?;
+                fs::write(file, json)
+                    .map_err(|error| format!("could not write {}: {}", file, error))?;
+                Ok(format!("saved snapshot to {}", file))
+            }
+            ["load", file] => {
+                let json = fs::read_to_string(file)
+                    .map_err(|error| format!("could not read {}: {}", file, error))?;
+                let state = IntcodeState::from_json(&json).map_err(|error| error.to_string())?;
+                self.restore(state);
+                Ok(self.describe_position())
+            }
+            ["i"] | ["info"] => Ok(format!(
+                "pc: {}\nrb: {}\nstatus: {:?}\ninput: {:?}\noutput: {:?}",
+                self.state.index,
+                self.state.relative_base,
+                self.status,
+                self.state.input,
+                self.state.output
+            )),
+            ["h"] | ["help"] => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command `{}`, try `help`", command.trim())),
+        }
+    }
+
+    fn describe_position(&self) -> String {
+        match &self.status {
+            Status::Ready if self.at_breakpoint() => {
+                format!("breakpoint\n=> {}", self.current_instruction())
+            }
+            Status::Ready => format!("=> {}", self.current_instruction()),
+            Status::WaitingForInput => {
+                format!("waiting for input\n=> {}", self.current_instruction())
+            }
+            Status::BudgetExhausted => {
+                format!("step budget exhausted\n=> {}", self.current_instruction())
+            }
+            Status::Halted => format!("halted, output: {:?}", self.state.output),
+            Status::Failed(error) => {
+                format!("error: {}\n=> {}", error, self.current_instruction())
+            }
+        }
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse()
+        .map_err(|_| format!("invalid number `{}`", text))
+}
+
+fn parse_opcode(text: &str) -> Result<i64, String> {
+    if let Ok(opcode) = text.parse() {
+        return Ok(opcode);
+    }
+
+    registry::BUILTINS
+        .iter()
+        .find(|builtin| builtin.mnemonic == text)
+        .map(|builtin| builtin.opcode as i64)
+        .ok_or_else(|| format!("unknown opcode `{}`", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+
+    #[test]
+    fn test_step_and_wait_for_input() {
+        let mut debugger = Debugger::new(IntcodeState::from(adder()));
+
+        assert_eq!(
+            debugger.execute("s"),
+            Ok("waiting for input\n=>     0: in [11]".to_string())
+        );
+        assert_eq!(debugger.execute("in 3 4"), Ok("input: [3, 4]".to_string()));
+        assert_eq!(
+            debugger.execute("step 2"),
+            Ok("=>     4: add [11], [12], [13]".to_string())
+        );
+        assert_eq!(debugger.state().index(), 4);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::new(
+            IntcodeState::from(adder())
+                .set_next_input(3)
+                .set_next_input(4),
+        );
+
+        debugger.execute("b 4").unwrap();
+        debugger.execute("bo out").unwrap();
+        assert_eq!(
+            debugger.execute("c"),
+            Ok("breakpoint\n=>     4: add [11], [12], [13]".to_string())
+        );
+        assert_eq!(
+            debugger.execute("c"),
+            Ok("breakpoint\n=>     8: out [13]".to_string())
+        );
+        assert_eq!(
+            debugger.execute("d 4"),
+            Ok("deleted breakpoint 4".to_string())
+        );
+        assert_eq!(debugger.execute("c"), Ok("halted, output: [7]".to_string()));
+        assert_eq!(debugger.status(), &Status::Halted);
+    }
+
+    #[test]
+    fn test_inspect_and_poke_memory() {
+        let mut debugger = Debugger::new(
+            IntcodeState::from(adder())
+                .set_next_input(3)
+                .set_next_input(4),
+        );
+
+        debugger.execute("poke 6 11").unwrap();
+        assert_eq!(
+            debugger.execute("x 4 3"),
+            Ok("    4: 1\n    5: 11\n    6: 11".to_string())
+        );
+        assert_eq!(debugger.execute("x 20"), Ok("   20: 0".to_string()));
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.state().output, vec![6]);
+    }
+
+    #[test]
+    fn test_memory_bounds() {
+        let mut debugger = Debugger::new(IntcodeState::from(adder()).with_memory_limit(100));
+
+        assert_eq!(
+            debugger.execute("poke 5000 1"),
+            Err("address 5000 exceeds the memory limit of 100".to_string())
+        );
+        assert_eq!(
+            debugger.poke(100, 1),
+            Err(IntcodeErrorKind::MemoryLimitExceeded {
+                address: 100,
+                limit: 100
+            })
+        );
+        assert_eq!(debugger.state().code.len(), 14);
+        assert!(debugger.poke(99, 1).is_ok());
+
+        assert_eq!(
+            debugger.execute("x 5 18446744073709551615"),
+            Err("18446744073709551615 cells from 5 exceed the address space".to_string())
+        );
+        assert_eq!(
+            debugger.execute("x 0 1000000000"),
+            Err("1000000000 cells from 0 exceed the memory limit of 100".to_string())
+        );
+        assert!(debugger.execute("x 90 10").is_ok());
+    }
+
+    #[test]
+    fn test_poke_after_failure() {
+        let mut debugger = Debugger::new(IntcodeState::from(vec![1, -1, 0, 0, 99]));
+        assert!(debugger
+            .execute("s")
+            .unwrap()
+            .contains("negative address -1"));
+
+        debugger.execute("poke 1 0").unwrap();
+        assert_eq!(debugger.status(), &Status::Ready);
+        assert_eq!(debugger.execute("s"), Ok("=>     4: hlt".to_string()));
+    }
+
+    #[test]
+    fn test_list_and_info() {
+        let mut debugger = Debugger::new(IntcodeState::from(adder()));
+
+        assert_eq!(
+            debugger.execute("l 3"),
+            Ok("    0: in [11]\n    2: in [12]\n    4: add [11], [12], [13]".to_string())
+        );
+        assert_eq!(
+            debugger.execute("info"),
+            Ok("pc: 0\nrb: 0\nstatus: Ready\ninput: []\noutput: []".to_string())
+        );
+    }
+
+    #[test]
+    fn test_keeps_state_on_error() {
+        let mut debugger = Debugger::new(IntcodeState::from(vec![1101, 1, 1, 7, 1, -1, 0, 0]));
+
+        debugger.execute("s 5").unwrap();
+        assert_eq!(
+            debugger.execute("s"),
+            Ok("error: pc 4 (instruction 1), parameter 1: negative address -1\n=>     4: add [-1], [0], [2]".to_string())
+        );
+        assert_eq!(
+            debugger.status(),
+            &Status::Failed(IntcodeError {
+                pc: 4,
+                instruction: 1,
+                parameter: Some(1),
+                kind: IntcodeErrorKind::NegativeAddress(-1),
+            })
+        );
+        assert_eq!(debugger.state().index(), 4);
+        assert_eq!(debugger.peek(7), 2);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut debugger = Debugger::new(
+            IntcodeState::from(adder())
+                .set_next_input(3)
+                .set_next_input(4),
+        );
+
+        debugger.execute("c").unwrap();
+        assert_eq!(
+            debugger.execute("back 2"),
+            Ok("back 2 steps\n=>     4: add [11], [12], [13]".to_string())
+        );
+        assert!(debugger.state().output.is_empty());
+        assert_eq!(
+            debugger.execute("bw 12"),
+            Ok("back 1 steps\n=>     2: in [12]".to_string())
+        );
+        assert_eq!(debugger.state().input, vec![4]);
+        assert_eq!(
+            debugger.execute("bw 13"),
+            Err("no recorded write to 13".to_string())
+        );
+        assert_eq!(
+            debugger.execute("bs 5"),
+            Ok("back 1 steps\n=>     0: in [11]".to_string())
+        );
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.state().output, vec![7]);
+    }
+
+    #[test]
+    fn test_save_and_load_snapshot() {
+        let file = std::env::temp_dir().join(format!("intcode-dbg-{}.json", std::process::id()));
+        let file = file.to_str().unwrap();
+        let mut debugger = Debugger::new(IntcodeState::from(adder()).set_next_input(3));
+
+        debugger.execute("s").unwrap();
+        debugger.execute(&format!("save {}", file)).unwrap();
+        debugger.execute("in 4").unwrap();
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.state().output, vec![7]);
+
+        assert_eq!(
+            debugger.execute(&format!("load {}", file)),
+            Ok("=>     2: in [12]".to_string())
+        );
+        fs::remove_file(file).unwrap();
+        debugger.execute("in 10").unwrap();
+        debugger.execute("c").unwrap();
+        assert_eq!(debugger.state().output, vec![13]);
+    }
+
+    #[test]
+    fn test_invalid_commands() {
+        let mut debugger = Debugger::new(IntcodeState::from(adder()));
+
+        assert_eq!(
+            debugger.execute("bo foo"),
+            Err("unknown opcode `foo`".to_string())
+        );
+        assert_eq!(
+            debugger.execute("x a"),
+            Err("invalid number `a`".to_string())
+        );
+        assert_eq!(debugger.execute("d 3"), Err("no breakpoint 3".to_string()));
+        assert_eq!(
+            debugger.execute("jump"),
+            Err("unknown command `jump`, try `help`".to_string())
+        );
+    }
+}
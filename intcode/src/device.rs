@@ -0,0 +1,269 @@
+use crate::io::IntcodeIo;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+pub trait Device {
+    // Number of output values that make up one message to the device
+    fn arity(&self) -> usize;
+    fn receive(&mut self, message: &[i64]);
+    // The next input for the machine, `None` makes it wait
+    fn send(&mut self) -> Option<i64>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DeviceError {
+    ZeroArity,
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceError::ZeroArity => write!(f, "a device message needs at least one value"),
+        }
+    }
+}
+
+impl Error for DeviceError {}
+
+// Connects a device to the input and output instructions of a machine. Output
+// values are collected until they form a complete message
+pub struct DeviceIo<D: Device> {
+    device: D,
+    pending: Vec<i64>,
+}
+
+impl<D: Device> DeviceIo<D> {
+    pub fn new(device: D) -> Result<DeviceIo<D>, DeviceError> {
+        if device.arity() == 0 {
+            return Err(DeviceError::ZeroArity);
+        }
+
+        Ok(DeviceIo {
+            device,
+            pending: vec![],
+        })
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_device(self) -> D {
+        self.device
+    }
+
+    pub fn pending(&self) -> &[i64] {
+        &self.pending
+    }
+}
+
+impl<D: Device> IntcodeIo for DeviceIo<D> {
+    fn read(&mut self) -> Option<i64> {
+        self.device.send()
+    }
+
+    fn write(&mut self, value: i64) {
+        self.pending.push(value);
+        if self.pending.len() == self.device.arity() {
+            self.device.receive(&self.pending);
+            self.pending.clear();
+        }
+    }
+}
+
+// Receives (x, y, value) triples, like a screen
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Grid {
+    cells: BTreeMap<(i64, i64), i64>,
+    input: VecDeque<i64>,
+}
+
+impl Grid {
+    pub fn new() -> Grid {
+        Grid::default()
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> i64 {
+        self.cells.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, x: i64, y: i64, value: i64) {
+        self.cells.insert((x, y), value);
+    }
+
+    pub fn push_input(&mut self, input: i64) {
+        self.input.push_back(input);
+    }
+
+    // Cells that were ever written, whatever their value
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn count(&self, value: i64) -> usize {
+        self.cells.values().filter(|&&cell| cell == value).count()
+    }
+
+    // Rows from the smallest to the largest written y, cells never written
+    // are drawn as 0
+    pub fn render(&self, glyph: impl Fn(i64) -> char) -> String {
+        let xs = self.cells.keys().map(|&(x, _)| x);
+        let ys = self.cells.keys().map(|&(_, y)| y);
+        let (min_x, max_x) = match (xs.clone().min(), xs.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return String::new(),
+        };
+        let (min_y, max_y) = (ys.clone().min().unwrap(), ys.max().unwrap());
+
+        (min_y..=max_y)
+            .map(|y| {
+                let row: String = (min_x..=max_x).map(|x| glyph(self.get(x, y))).collect();
+                row + "\n"
+            })
+            .collect()
+    }
+}
+
+impl Device for Grid {
+    fn arity(&self) -> usize {
+        3
+    }
+
+    fn receive(&mut self, message: &[i64]) {
+        self.set(message[0], message[1], message[2]);
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        self.input.pop_front()
+    }
+}
+
+// Reads the color of the panel below it, then receives the color to paint
+// and a turn, 0 for left and 1 for right, and moves one panel forward
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PaintingRobot {
+    pub panels: Grid,
+    position: (i64, i64),
+    direction: (i64, i64),
+}
+
+impl PaintingRobot {
+    pub fn new() -> PaintingRobot {
+        PaintingRobot {
+            panels: Grid::new(),
+            position: (0, 0),
+            direction: (0, -1),
+        }
+    }
+
+    pub fn position(&self) -> (i64, i64) {
+        self.position
+    }
+}
+
+impl Device for PaintingRobot {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn receive(&mut self, message: &[i64]) {
+        let (x, y) = self.position;
+        self.panels.set(x, y, message[0]);
+
+        let (dx, dy) = self.direction;
+        self.direction = if message[1] == 0 {
+            (dy, -dx)
+        } else {
+            (-dy, dx)
+        };
+        self.position = (x + self.direction.0, y + self.direction.1);
+    }
+
+    fn send(&mut self) -> Option<i64> {
+        Some(self.panels.get(self.position.0, self.position.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::Machine;
+    use crate::Outcome;
+
+    // Reads an input before every pair of outputs, like a robot program would
+    fn paint(pairs: &[(i64, i64)]) -> Vec<i64> {
+        let mut code: Vec<i64> = pairs
+            .iter()
+            .flat_map(|&(color, turn)| vec![3, 1000, 104, color, 104, turn])
+            .collect();
+        code.push(99);
+        code
+    }
+
+    #[test]
+    fn test_painting_robot() {
+        let code = paint(&[(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)]);
+        let mut io = DeviceIo::new(PaintingRobot::new()).unwrap();
+
+        let outcome = Machine::new(&code).run_with_io(&mut io).unwrap();
+        assert_eq!(outcome, Outcome::Halted);
+
+        let robot = io.into_device();
+        assert_eq!(robot.panels.len(), 6);
+        assert_eq!(robot.position(), (0, -1));
+        assert_eq!(
+            robot
+                .panels
+                .render(|color| if color == 1 { '#' } else { '.' }),
+            "..#\n..#\n##.\n"
+        );
+    }
+
+    #[test]
+    fn test_grid_and_partial_messages() {
+        // Draws two tiles, then the first value of a third before waiting
+        let code = vec![
+            104, 1, 104, 2, 104, 3, 104, 6, 104, 5, 104, 4, 104, 7, 3, 0, 99,
+        ];
+        let mut io = DeviceIo::new(Grid::new()).unwrap();
+        let mut machine = Machine::new(&code);
+
+        assert_eq!(machine.run_with_io(&mut io), Ok(Outcome::WaitingForInput));
+        assert_eq!(io.device().get(1, 2), 3);
+        assert_eq!(io.device().get(6, 5), 4);
+        assert_eq!(io.device().count(3), 1);
+        assert_eq!(io.pending(), &[7]);
+
+        io.device_mut().push_input(1);
+        assert_eq!(machine.run_with_io(&mut io), Ok(Outcome::Halted));
+        assert!(machine.output().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_zero_arity() {
+        struct Sink;
+
+        impl Device for Sink {
+            fn arity(&self) -> usize {
+                0
+            }
+
+            fn receive(&mut self, _: &[i64]) {}
+
+            fn send(&mut self) -> Option<i64> {
+                None
+            }
+        }
+
+        assert_eq!(DeviceIo::new(Sink).err(), Some(DeviceError::ZeroArity));
+    }
+}
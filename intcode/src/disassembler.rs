@@ -0,0 +1,260 @@
+use crate::registry::{self, Registry};
+use crate::word::Word;
+use crate::{split_instruction, Memory, ParamMode};
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Instruction {
+        address: usize,
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+    },
+    Data {
+        address: usize,
+        value: i64,
+    },
+}
+
+impl Statement {
+    pub fn address(&self) -> usize {
+        match self {
+            Statement::Instruction { address, .. } | Statement::Data { address, .. } => *address,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => operands.len() + 1,
+            Statement::Data { .. } => 1,
+        }
+    }
+}
+
+impl Operand {
+    fn from(mode: &ParamMode, value: i64) -> Operand {
+        match mode {
+            ParamMode::Position => Operand::Position(value),
+            ParamMode::Immediate => Operand::Immediate(value),
+            ParamMode::Relative => Operand::Relative(value),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Position(value) => write!(f, "[{}]", value),
+            Operand::Immediate(value) => write!(f, "#{}", value),
+            Operand::Relative(value) if *value < 0 => write!(f, "[rb-{}]", value.unsigned_abs()),
+            Operand::Relative(value) => write!(f, "[rb+{}]", value),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Statement::Instruction {
+                address,
+                mnemonic,
+                operands,
+            } => {
+                write!(f, "{:>5}: {}", address, mnemonic)?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Statement::Data { address, value } => write!(f, "{:>5}: data {}", address, value),
+        }
+    }
+}
+
+// The mnemonic and parameter count of an opcode
+type Lookup<'a> = &'a dyn Fn(usize) -> Option<(&'static str, usize)>;
+
+fn builtin(opcode: usize) -> Option<(&'static str, usize)> {
+    registry::builtin(opcode).map(|builtin| (builtin.mnemonic, builtin.roles.len()))
+}
+
+pub fn disassemble(code: &Memory) -> Vec<Statement> {
+    disassemble_by(code, &builtin)
+}
+
+// Disassembles the instructions of `registry`, including the ones
+// registered on top of the built-in set
+pub fn disassemble_with<W: Word>(code: &Memory, registry: &Registry<W>) -> Vec<Statement> {
+    disassemble_by(code, &|opcode| {
+        registry
+            .get(opcode)
+            .map(|instruction| (instruction.mnemonic(), instruction.roles().len()))
+    })
+}
+
+fn disassemble_by(code: &Memory, lookup: Lookup) -> Vec<Statement> {
+    let mut statements = vec![];
+    let mut address = 0;
+
+    while address < code.len() {
+        let statement = statement_by(code, address, lookup);
+        address += statement.size();
+        statements.push(statement);
+    }
+
+    statements
+}
+
+pub fn listing(code: &Memory) -> String {
+    to_listing(disassemble(code))
+}
+
+pub fn listing_with<W: Word>(code: &Memory, registry: &Registry<W>) -> String {
+    to_listing(disassemble_with(code, registry))
+}
+
+fn to_listing(statements: Vec<Statement>) -> String {
+    statements
+        .iter()
+        .map(|statement| format!("{}\n", statement))
+        .collect()
+}
+
+pub fn statement_at(code: &Memory, address: usize) -> Statement {
+    statement_by(code, address, &builtin)
+}
+
+fn statement_by(code: &Memory, address: usize, lookup: Lookup) -> Statement {
+    decode_at(code, address, lookup).unwrap_or(Statement::Data {
+        address,
+        value: code.get(address).copied().unwrap_or(0),
+    })
+}
+
+fn decode_at(code: &Memory, address: usize, lookup: Lookup) -> Option<Statement> {
+    let word = *code.get(address)?;
+    let (opcode, param_modes) = split_instruction(word).ok()?;
+    let (mnemonic, arity) = lookup(opcode)?;
+
+    // Mode digits for parameters the instruction does not have would get lost
+    if word / 10_i64.pow(2 + arity as u32) != 0 {
+        return None;
+    }
+
+    let operands = param_modes[..arity]
+        .iter()
+        .enumerate()
+        .map(|(i, mode)| Some(Operand::from(mode, *code.get(address + i + 1)?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(Statement::Instruction {
+        address,
+        mnemonic,
+        operands,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_listing_website() {
+        assert_eq!(
+            listing(&vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+            "    0: add [9], [10], [3]\n\
+             \x20   4: mul [3], [11], [0]\n\
+             \x20   8: hlt\n\
+             \x20   9: data 30\n\
+             \x20  10: data 40\n\
+             \x20  11: data 50\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_parameter_modes() {
+        assert_eq!(
+            disassemble(&vec![1101, 100, -1, 4, 204, -3, 21107, 1, 2, 5]),
+            vec![
+                Statement::Instruction {
+                    address: 0,
+                    mnemonic: "add",
+                    operands: vec![
+                        Operand::Immediate(100),
+                        Operand::Immediate(-1),
+                        Operand::Position(4),
+                    ],
+                },
+                Statement::Instruction {
+                    address: 4,
+                    mnemonic: "out",
+                    operands: vec![Operand::Relative(-3)],
+                },
+                Statement::Instruction {
+                    address: 6,
+                    mnemonic: "lt",
+                    operands: vec![
+                        Operand::Immediate(1),
+                        Operand::Immediate(2),
+                        Operand::Relative(5),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_listing_operands() {
+        assert_eq!(
+            listing(&vec![3, 12, 1005, 12, 7, 109, 19, 204, 3]),
+            "    0: in [12]\n\
+             \x20   2: jnz [12], #7\n\
+             \x20   5: arb #19\n\
+             \x20   7: out [rb+3]\n"
+        );
+        assert_eq!(
+            listing(&vec![204, i64::MIN]),
+            "    0: out [rb-9223372036854775808]\n"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_data() {
+        assert_eq!(
+            disassemble(&vec![-1, 123456, 10099, 55, 1, 0]),
+            vec![
+                Statement::Data {
+                    address: 0,
+                    value: -1
+                },
+                Statement::Data {
+                    address: 1,
+                    value: 123456
+                },
+                Statement::Data {
+                    address: 2,
+                    value: 10099
+                },
+                Statement::Data {
+                    address: 3,
+                    value: 55
+                },
+                Statement::Data {
+                    address: 4,
+                    value: 1
+                },
+                Statement::Data {
+                    address: 5,
+                    value: 0
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::registry::Role;
+use crate::word::{saturating_i64, Word};
+use crate::IntcodeState;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IntcodeError {
+    pub pc: usize,
+    pub instruction: i64,
+    pub parameter: Option<usize>,
+    pub kind: IntcodeErrorKind,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum IntcodeErrorKind {
+    UnknownOpcode(i64),
+    NegativeInstruction,
+    InstructionTooLong,
+    UnknownParamMode(i64),
+    ImmediateWrite,
+    NegativeAddress(i64),
+    MemoryLimitExceeded { address: usize, limit: usize },
+    InvalidJumpTarget(i64),
+    ResumeAfterHalt,
+    InfiniteLoop { cycle_length: u64 },
+    Overflow,
+    UndeclaredParameter { arity: usize },
+    WrongRole { declared: Role },
+}
+
+impl IntcodeError {
+    // `index` is the memory location the failing parameter was read from
+    pub(crate) fn at<W: Word>(
+        intcode_state: &IntcodeState<W>,
+        index: usize,
+        kind: IntcodeErrorKind,
+    ) -> Self {
+        let pc = intcode_state.index;
+        IntcodeError {
+            pc,
+            instruction: intcode_state.code.get(pc).map_or(0, saturating_i64),
+            parameter: if index > pc { Some(index - pc) } else { None },
+            kind,
+        }
+    }
+
+    pub(crate) fn decoding(
+        instruction: i64,
+        parameter: Option<usize>,
+        kind: IntcodeErrorKind,
+    ) -> Self {
+        IntcodeError {
+            pc: 0,
+            instruction,
+            parameter,
+            kind,
+        }
+    }
+
+    pub(crate) fn at_pc(self, pc: usize) -> Self {
+        IntcodeError { pc, ..self }
+    }
+}
+
+impl fmt::Display for IntcodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntcodeErrorKind::UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            IntcodeErrorKind::NegativeInstruction => write!(f, "negative instruction"),
+            IntcodeErrorKind::InstructionTooLong => {
+                write!(f, "instruction has more than five digits")
+            }
+            IntcodeErrorKind::UnknownParamMode(mode) => {
+                write!(f, "unknown parameter mode {}", mode)
+            }
+            IntcodeErrorKind::ImmediateWrite => write!(f, "write parameter in immediate mode"),
+            IntcodeErrorKind::NegativeAddress(address) => {
+                write!(f, "negative address {}", address)
+            }
+            IntcodeErrorKind::MemoryLimitExceeded { address, limit } => write!(
+                f,
+                "address {} exceeds the memory limit of {}",
+                address, limit
+            ),
+            IntcodeErrorKind::InvalidJumpTarget(target) => {
+                write!(f, "invalid jump target {}", target)
+            }
+            IntcodeErrorKind::ResumeAfterHalt => write!(f, "cannot resume a halted program"),
+            IntcodeErrorKind::InfiniteLoop { cycle_length } => {
+                write!(f, "infinite loop with a cycle of {} steps", cycle_length)
+            }
+            IntcodeErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            IntcodeErrorKind::UndeclaredParameter { arity } => {
+                write!(f, "the instruction only has {} parameters", arity)
+            }
+            IntcodeErrorKind::WrongRole { declared } => match declared {
+                Role::Read => write!(f, "the parameter is declared as read"),
+                Role::Write => write!(f, "the parameter is declared as write"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for IntcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "pc {} (instruction {})", self.pc, self.instruction)?;
+        if let Some(parameter) = self.parameter {
+            write!(f, ", parameter {}", parameter)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl Error for IntcodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let error = IntcodeError {
+            pc: 12,
+            instruction: 1101,
+            parameter: Some(3),
+            kind: IntcodeErrorKind::NegativeAddress(-5),
+        };
+        assert_eq!(
+            error.to_string(),
+            "pc 12 (instruction 1101), parameter 3: negative address -5"
+        );
+
+        let error = IntcodeError {
+            pc: 4,
+            instruction: 42,
+            parameter: None,
+            kind: IntcodeErrorKind::UnknownOpcode(42),
+        };
+        assert_eq!(
+            error.to_string(),
+            "pc 4 (instruction 42): unknown opcode 42"
+        );
+    }
+}
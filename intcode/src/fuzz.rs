@@ -0,0 +1,518 @@
+use crate::history::History;
+use crate::machine::Machine;
+use crate::profiler::Profiler;
+use crate::registry::Registry;
+use crate::{
+    intcode_step, run_profiled, run_recorded, IntcodeError, IntcodeErrorKind, IntcodeResult,
+    IntcodeReturnType, IntcodeState, Memory, Outcome, Stop,
+};
+use std::fmt;
+
+pub const DEFAULT_SEED: u64 = 0x2019_1205;
+pub const STEP_BUDGET: u64 = 500;
+pub const MEMORY_LIMIT: usize = 256;
+
+pub type RunResult = Result<(Outcome, IntcodeState), IntcodeError>;
+
+// SplitMix64, good enough to generate programs and the same on every machine
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    pub fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low) as u64 + 1) as i64
+    }
+
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Case {
+    pub code: Memory,
+    pub inputs: Vec<i64>,
+}
+
+impl Case {
+    pub fn state(&self) -> IntcodeState {
+        IntcodeState::with_inputs(self.code.clone(), self.inputs.clone())
+            .with_memory_limit(MEMORY_LIMIT)
+            .with_step_budget(STEP_BUDGET)
+    }
+}
+
+fn operand(rng: &mut Rng, code_len: i64) -> i64 {
+    match rng.below(10) {
+        0 => rng.range(-3, 3),
+        1 => [i64::MAX, i64::MIN, i64::MAX / 2][rng.below(3) as usize],
+        2..=5 => rng.range(0, code_len + 8),
+        _ => rng.range(-20, 40),
+    }
+}
+
+// Instructions with valid opcodes and modes that end in a halt. Addresses and
+// values are random, so running them can still fail
+pub fn well_formed(rng: &mut Rng) -> Memory {
+    let instructions = rng.range(1, 12);
+    let code_len = instructions * 4 + 1;
+    let mut code = vec![];
+
+    for _ in 0..instructions {
+        let opcode = rng.range(1, 9);
+        let (reads, writes) = match opcode {
+            1 | 2 | 7 | 8 => (2, 1),
+            3 => (0, 1),
+            4 | 9 => (1, 0),
+            _ => (2, 0),
+        };
+        let mut modes = vec![];
+        modes.extend((0..reads).map(|_| rng.range(0, 2)));
+        modes.extend((0..writes).map(|_| if rng.chance(70) { 0 } else { 2 }));
+
+        let mode_digits = modes
+            .iter()
+            .rev()
+            .fold(0, |digits, mode| digits * 10 + mode);
+        code.push(mode_digits * 100 + opcode);
+        for (i, mode) in modes.iter().enumerate() {
+            let is_jump_target = (opcode == 5 || opcode == 6) && i == 1;
+            code.push(if is_jump_target && rng.chance(70) {
+                rng.range(0, code_len)
+            } else if *mode == 2 {
+                rng.range(-4, 8)
+            } else {
+                operand(rng, code_len)
+            });
+        }
+    }
+    code.push(99);
+
+    code
+}
+
+// A well formed program with some words replaced by broken instructions
+pub fn malformed(rng: &mut Rng) -> Memory {
+    let mut code = well_formed(rng);
+    for _ in 0..rng.range(1, 3) {
+        let index = rng.below(code.len() as u64) as usize;
+        code[index] = match rng.below(7) {
+            0 => rng.range(-5, -1),
+            1 => 300 + rng.range(1, 9),
+            2 => 11101,
+            3 => rng.range(10, 98),
+            4 => 100_000 + rng.range(0, 99),
+            5 => [i64::MAX, i64::MIN][rng.below(2) as usize],
+            _ => rng.range(-100, 100),
+        };
+    }
+
+    code
+}
+
+pub fn generate(rng: &mut Rng) -> Case {
+    let code = if rng.chance(50) {
+        well_formed(rng)
+    } else {
+        malformed(rng)
+    };
+    let inputs = (0..rng.below(4)).map(|_| rng.range(-10, 10)).collect();
+
+    Case { code, inputs }
+}
+
+fn finished(result: IntcodeResult) -> RunResult {
+    result.map(|return_type| match return_type {
+        IntcodeReturnType::Finished(state) => (Outcome::Halted, state),
+        IntcodeReturnType::Interrupted(state) => (Outcome::WaitingForInput, state),
+        IntcodeReturnType::BudgetExhausted(state) => (Outcome::BudgetExhausted, state),
+    })
+}
+
+// A deliberately plain interpreter that shares no code with the real one, so
+// the backends are checked against an independent reading of the spec
+pub fn reference(mut intcode_state: IntcodeState) -> RunResult {
+    loop {
+        if intcode_state.step_budget == Some(0) {
+            return Ok((Outcome::BudgetExhausted, intcode_state));
+        }
+        if let Some(outcome) = reference_step(&mut intcode_state)? {
+            return Ok((outcome, intcode_state));
+        }
+        if let Some(step_budget) = intcode_state.step_budget.as_mut() {
+            *step_budget -= 1;
+        }
+    }
+}
+
+// Executes one instruction, or returns why the machine stopped before it
+fn reference_step(state: &mut IntcodeState) -> Result<Option<Outcome>, IntcodeError> {
+    let pc = state.index;
+    // `parameter` counts from 1, 0 is the instruction itself
+    fn fail<T>(
+        state: &IntcodeState,
+        parameter: usize,
+        kind: IntcodeErrorKind,
+    ) -> Result<T, IntcodeError> {
+        Err(IntcodeError::at(state, state.index + parameter, kind))
+    }
+    let fetch = |state: &IntcodeState, address: usize| {
+        if address < state.memory_limit {
+            Ok(state.code.get(address).copied().unwrap_or(0))
+        } else {
+            fail(
+                state,
+                address - pc,
+                IntcodeErrorKind::MemoryLimitExceeded {
+                    address,
+                    limit: state.memory_limit,
+                },
+            )
+        }
+    };
+
+    let instruction = fetch(state, pc)?;
+    if instruction < 0 {
+        return fail(state, 0, IntcodeErrorKind::NegativeInstruction);
+    }
+    if instruction > 99999 {
+        return fail(state, 0, IntcodeErrorKind::InstructionTooLong);
+    }
+    let mut modes = [0; 3];
+    for (parameter, mode) in modes.iter_mut().enumerate() {
+        *mode = instruction / 10i64.pow(parameter as u32 + 2) % 10;
+        if *mode > 2 {
+            return fail(
+                state,
+                parameter + 1,
+                IntcodeErrorKind::UnknownParamMode(*mode),
+            );
+        }
+    }
+
+    // The address parameter `n` (counted from 1) points to
+    let address = |state: &IntcodeState, n: usize| -> Result<usize, IntcodeError> {
+        let word = fetch(state, pc + n)?;
+        let address = match modes[n - 1] {
+            0 => word,
+            2 => match state.relative_base.checked_add(word) {
+                Some(address) => address,
+                None => return fail(state, n, IntcodeErrorKind::Overflow),
+            },
+            _ => return fail(state, n, IntcodeErrorKind::ImmediateWrite),
+        };
+        if address < 0 {
+            return fail(state, n, IntcodeErrorKind::NegativeAddress(address));
+        }
+        let address = address as usize;
+        if address >= state.memory_limit {
+            return fail(
+                state,
+                n,
+                IntcodeErrorKind::MemoryLimitExceeded {
+                    address,
+                    limit: state.memory_limit,
+                },
+            );
+        }
+        Ok(address)
+    };
+    let value = |state: &IntcodeState, n: usize| -> Result<i64, IntcodeError> {
+        if modes[n - 1] == 1 {
+            fetch(state, pc + n)
+        } else {
+            let address = address(state, n)?;
+            Ok(state.code.get(address).copied().unwrap_or(0))
+        }
+    };
+    let store = |state: &mut IntcodeState, address: usize, word: i64| {
+        if state.code.len() <= address {
+            state.code.resize(address + 1, 0);
+        }
+        state.code[address] = word;
+    };
+
+    match instruction % 100 {
+        opcode @ (1 | 2 | 7 | 8) => {
+            let (a, b) = (value(state, 1)?, value(state, 2)?);
+            let target = address(state, 3)?;
+            let result = match opcode {
+                1 => a.checked_add(b),
+                2 => a.checked_mul(b),
+                7 => Some((a < b) as i64),
+                _ => Some((a == b) as i64),
+            };
+            match result {
+                Some(result) => store(state, target, result),
+                None => return fail(state, 0, IntcodeErrorKind::Overflow),
+            }
+            state.index += 4;
+        }
+        3 => {
+            let target = address(state, 1)?;
+            match state.input.pop_front() {
+                Some(input) => store(state, target, input),
+                None => return Ok(Some(Outcome::WaitingForInput)),
+            }
+            state.index += 2;
+        }
+        4 => {
+            let output = value(state, 1)?;
+            state.output.push(output);
+            state.index += 2;
+        }
+        opcode @ (5 | 6) => {
+            if (value(state, 1)? != 0) == (opcode == 5) {
+                let target = value(state, 2)?;
+                if target < 0 {
+                    return fail(state, 2, IntcodeErrorKind::InvalidJumpTarget(target));
+                }
+                state.index = target as usize;
+            } else {
+                state.index += 3;
+            }
+        }
+        9 => {
+            let adjustment = value(state, 1)?;
+            match state.relative_base.checked_add(adjustment) {
+                Some(relative_base) => state.relative_base = relative_base,
+                None => return fail(state, 0, IntcodeErrorKind::Overflow),
+            }
+            state.index += 2;
+        }
+        99 => return Ok(Some(Outcome::Halted)),
+        opcode => return fail(state, 0, IntcodeErrorKind::UnknownOpcode(opcode)),
+    }
+
+    Ok(None)
+}
+
+pub fn stepwise(mut intcode_state: IntcodeState) -> RunResult {
+    loop {
+        intcode_state = match intcode_step(intcode_state) {
+            Ok(t) => t,
+            Err(Stop::Returned(return_type)) => return finished(Ok(return_type)),
+            Err(Stop::Failed(error)) => return Err(error),
+        };
+    }
+}
+
+pub fn machine(intcode_state: IntcodeState) -> RunResult {
+    let mut machine = Machine::from(intcode_state);
+    let outcome = machine.run()?;
+
+    Ok((outcome, machine.into_state()))
+}
+
+pub fn cached_machine(intcode_state: IntcodeState) -> RunResult {
+    let mut machine = Machine::from(intcode_state).with_decode_cache();
+    let outcome = machine.run()?;
+
+    Ok((outcome, machine.into_state()))
+}
+
+pub fn recorded(intcode_state: IntcodeState) -> RunResult {
+    finished(run_recorded(intcode_state, &mut History::new()))
+}
+
+pub fn profiled(intcode_state: IntcodeState) -> RunResult {
+    finished(run_profiled(intcode_state, &mut Profiler::new()))
+}
+
+pub fn registry(intcode_state: IntcodeState) -> RunResult {
+    finished(Registry::builtin().run(intcode_state))
+}
+
+pub type Backend = fn(IntcodeState) -> RunResult;
+
+pub const BACKENDS: [(&str, Backend); 6] = [
+    ("stepwise", stepwise),
+    ("machine", machine),
+    ("cached machine", cached_machine),
+    ("recorded", recorded),
+    ("profiled", profiled),
+    ("registry", registry),
+];
+
+fn disagrees(case: &Case, backend: &dyn Fn(IntcodeState) -> RunResult) -> bool {
+    reference(case.state()) != backend(case.state())
+}
+
+// Removes chunks of words and inputs, then moves words closer to zero, for as
+// long as the backend still disagrees with the reference
+pub fn shrink(case: &Case, backend: &dyn Fn(IntcodeState) -> RunResult) -> Case {
+    let mut case = case.clone();
+
+    loop {
+        let mut candidates = vec![];
+        let mut chunk = case.code.len() / 2;
+        while chunk > 0 {
+            for start in (0..case.code.len()).step_by(chunk) {
+                let mut code = case.code.clone();
+                code.drain(start..(start + chunk).min(code.len()));
+                candidates.push(Case {
+                    code,
+                    inputs: case.inputs.clone(),
+                });
+            }
+            chunk /= 2;
+        }
+        for i in 0..case.inputs.len() {
+            let mut inputs = case.inputs.clone();
+            inputs.remove(i);
+            candidates.push(Case {
+                code: case.code.clone(),
+                inputs,
+            });
+        }
+        for (i, &word) in case.code.iter().enumerate() {
+            for simpler in [0, word / 2] {
+                if simpler != word {
+                    let mut code = case.code.clone();
+                    code[i] = simpler;
+                    candidates.push(Case {
+                        code,
+                        inputs: case.inputs.clone(),
+                    });
+                }
+            }
+        }
+
+        match candidates
+            .into_iter()
+            .find(|candidate| disagrees(candidate, backend))
+        {
+            Some(smaller) => case = smaller,
+            None => return case,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub case_number: usize,
+    pub original: Case,
+    pub shrunk: Case,
+    pub expected: RunResult,
+    pub actual: RunResult,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "case {}: code {:?} with inputs {:?}",
+            self.case_number, self.shrunk.code, self.shrunk.inputs
+        )?;
+        writeln!(f, "reference: {:?}", self.expected)?;
+        write!(f, "backend:   {:?}", self.actual)
+    }
+}
+
+pub struct Fuzzer {
+    rng: Rng,
+    cases: usize,
+}
+
+impl Fuzzer {
+    pub fn new(seed: u64, cases: usize) -> Fuzzer {
+        Fuzzer {
+            rng: Rng::new(seed),
+            cases,
+        }
+    }
+
+    pub fn run(&mut self, backend: &dyn Fn(IntcodeState) -> RunResult) -> Result<(), Box<Failure>> {
+        for case_number in 0..self.cases {
+            let case = generate(&mut self.rng);
+            if disagrees(&case, backend) {
+                let shrunk = shrink(&case, backend);
+                return Err(Box::new(Failure {
+                    case_number,
+                    expected: reference(shrunk.state()),
+                    actual: backend(shrunk.state()),
+                    original: case,
+                    shrunk,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CASES: usize = 2000;
+
+    #[test]
+    fn test_backends_agree_with_reference() {
+        for (name, backend) in BACKENDS.iter() {
+            let result = Fuzzer::new(DEFAULT_SEED, CASES).run(backend);
+            if let Err(failure) = result {
+                panic!("{}: {}", name, failure);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generation_is_reproducible() {
+        let cases = |seed| {
+            let mut rng = Rng::new(seed);
+            (0..20).map(|_| generate(&mut rng)).collect::<Vec<_>>()
+        };
+        assert_eq!(cases(7), cases(7));
+        assert_ne!(cases(7), cases(8));
+
+        // The generated programs reach all kinds of outcomes
+        let mut rng = Rng::new(DEFAULT_SEED);
+        let results: Vec<RunResult> = (0..CASES)
+            .map(|_| reference(generate(&mut rng).state()))
+            .collect();
+        let outcome = |expected| {
+            results
+                .iter()
+                .any(|result| matches!(result, Ok((outcome, _)) if *outcome == expected))
+        };
+        assert!(outcome(Outcome::Halted));
+        assert!(outcome(Outcome::WaitingForInput));
+        assert!(outcome(Outcome::BudgetExhausted));
+        assert!(results.iter().any(Result::is_err));
+    }
+
+    #[test]
+    fn test_shrinks_to_a_minimal_reproducer() {
+        // Gets the sign of every output wrong
+        let broken = |intcode_state: IntcodeState| {
+            reference(intcode_state).map(|(outcome, mut state)| {
+                state.output.iter_mut().for_each(|value| *value = -*value);
+                (outcome, state)
+            })
+        };
+
+        let failure = Fuzzer::new(DEFAULT_SEED, CASES).run(&broken).unwrap_err();
+        assert!(failure.shrunk.code.len() <= failure.original.code.len());
+        assert!(failure.shrunk.code.len() <= 3, "{}", failure);
+        assert_eq!(failure.shrunk.code[0] % 100, 4);
+        assert!(failure.shrunk.inputs.is_empty());
+        assert_ne!(failure.expected, failure.actual);
+    }
+}
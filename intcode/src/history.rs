@@ -0,0 +1,199 @@
+use crate::{
+    get_index_value, get_target_index, intcode_step, IntcodeState, ProgramState, StepResult,
+};
+
+// Everything a single step changed, so it can be undone without keeping
+// copies of the memory around
+#[derive(Debug, PartialEq, Clone)]
+struct StepRecord {
+    pc: usize,
+    relative_base: i64,
+    step_budget: Option<u64>,
+    write: Option<MemoryWrite>,
+    input: Option<i64>,
+    output: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct MemoryWrite {
+    address: usize,
+    old_value: i64,
+    old_len: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    records: Vec<StepRecord>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn step(&mut self, intcode_state: IntcodeState) -> StepResult {
+        let write = write_address(&intcode_state).map(|address| MemoryWrite {
+            address,
+            old_value: intcode_state.code.get(address).copied().unwrap_or(0),
+            old_len: intcode_state.code.len(),
+        });
+        let mut record = StepRecord {
+            pc: intcode_state.index,
+            relative_base: intcode_state.relative_base,
+            step_budget: intcode_state.step_budget,
+            write,
+            input: None,
+            output: false,
+        };
+        let next_input = intcode_state.input.front().copied();
+        let input_len = intcode_state.input.len();
+        let output_len = intcode_state.output.len();
+
+        let new_state = intcode_step(intcode_state)?;
+
+        if new_state.input.len() < input_len {
+            record.input = next_input;
+        }
+        record.output = new_state.output.len() > output_len;
+        self.records.push(record);
+
+        Ok(new_state)
+    }
+
+    pub fn rewind(&mut self, mut intcode_state: IntcodeState, steps: usize) -> IntcodeState {
+        for _ in 0..steps {
+            let record = match self.records.pop() {
+                Some(record) => record,
+                None => break,
+            };
+
+            intcode_state.index = record.pc;
+            intcode_state.relative_base = record.relative_base;
+            intcode_state.step_budget = record.step_budget;
+            if let Some(write) = record.write {
+                intcode_state.code[write.address] = write.old_value;
+                intcode_state.code.truncate(write.old_len);
+            }
+            if let Some(input) = record.input {
+                intcode_state.input.push_front(input);
+            }
+            if record.output {
+                intcode_state.output.pop();
+            }
+        }
+
+        intcode_state
+    }
+
+    // Number of steps to rewind to get back to just before the last write to `address`
+    pub fn steps_since_write(&self, address: usize) -> Option<usize> {
+        self.records
+            .iter()
+            .rposition(|record| record.write.as_ref().map(|write| write.address) == Some(address))
+            .map(|position| self.records.len() - position)
+    }
+
+    pub fn rewind_to_write(
+        &mut self,
+        intcode_state: IntcodeState,
+        address: usize,
+    ) -> Result<IntcodeState, IntcodeState> {
+        match self.steps_since_write(address) {
+            Some(steps) => Ok(self.rewind(intcode_state, steps)),
+            None => Err(intcode_state),
+        }
+    }
+}
+
+fn write_address(intcode_state: &IntcodeState) -> Option<usize> {
+    let pc = intcode_state.index;
+    let instruction = get_index_value(intcode_state, pc).ok()?;
+    let program_state = ProgramState::from_memory_location(instruction).ok()?;
+    let parameter = program_state.write_param()?;
+    let mode = program_state.param_modes()[parameter];
+
+    get_target_index(intcode_state, pc + parameter + 1, mode).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::countdown;
+    use crate::{run_recorded, IntcodeReturnType, Stop};
+
+    fn every_state(mut intcode_state: IntcodeState) -> Vec<IntcodeState> {
+        let mut states = vec![intcode_state.clone()];
+        loop {
+            intcode_state = match intcode_step(intcode_state) {
+                Ok(state) => state,
+                Err(Stop::Returned(IntcodeReturnType::Finished(_))) => return states,
+                Err(stop) => panic!("wrong enum variant {:?}", stop),
+            };
+            states.push(intcode_state.clone());
+        }
+    }
+
+    #[test]
+    fn test_rewind_restores_every_step() {
+        let states = every_state(IntcodeState::with_next_input(countdown(), 3));
+        let mut history = History::new();
+        let mut state = states[0].clone();
+        for _ in 1..states.len() {
+            state = history.step(state).unwrap();
+        }
+        assert_eq!(history.len(), states.len() - 1);
+
+        for expected in states.iter().rev().skip(1) {
+            state = history.rewind(state, 1);
+            assert_eq!(&state, expected);
+        }
+        assert!(history.is_empty());
+        assert_eq!(history.rewind(state.clone(), 5), state);
+    }
+
+    #[test]
+    fn test_rewind_and_replay() {
+        let mut history = History::new();
+        let result = run_recorded(IntcodeState::with_next_input(countdown(), 2), &mut history);
+        let finished = match result {
+            Ok(IntcodeReturnType::Finished(state)) => state,
+            result => panic!("wrong enum variant {:?}", result),
+        };
+        assert_eq!(finished.output, vec![2, 1]);
+
+        let state = history.rewind(finished, 4);
+        assert_eq!(state.index(), 8);
+        assert_eq!(state.output, vec![2]);
+        assert_eq!(state.code[100], 1);
+    }
+
+    #[test]
+    fn test_rewind_to_write() {
+        let mut history = History::new();
+        let mut state = IntcodeState::with_next_input(countdown(), 3);
+        for _ in 0..5 {
+            state = history.step(state).unwrap();
+        }
+
+        let state = history.rewind_to_write(state, 100).unwrap();
+        assert_eq!(state.index(), 4);
+        assert_eq!(state.code[100], 3);
+        assert_eq!(history.len(), 2);
+
+        let state = history.rewind_to_write(state, 100).unwrap();
+        assert_eq!(state.index(), 0);
+        assert_eq!(state.input, vec![3]);
+        assert_eq!(state.code.len(), 12);
+
+        assert!(history.rewind_to_write(state, 100).is_err());
+    }
+}
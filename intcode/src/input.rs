@@ -3,7 +3,10 @@ use std::fs;
 use std::str::FromStr;
 
 pub fn get_input_vec(day: &str) -> Memory {
-    let filename = format!("./{}/resources/input", day);
+    get_memory_from_file(&format!("./{}/resources/input", day))
+}
+
+pub fn get_memory_from_file(filename: &str) -> Memory {
     let code: Memory = fs::read_to_string(filename)
         .expect("Something went wrong reading the file")
         .split(',')
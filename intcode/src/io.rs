@@ -0,0 +1,163 @@
+use crate::IntcodeState;
+use std::collections::VecDeque;
+use std::mem;
+
+pub trait IntcodeIo<W = i64> {
+    fn read(&mut self) -> Option<W>;
+    fn write(&mut self, value: W);
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct QueueIo<W = i64> {
+    pub input: VecDeque<W>,
+    pub output: Vec<W>,
+}
+
+impl<W> QueueIo<W> {
+    pub fn take_from(intcode_state: &mut IntcodeState<W>) -> QueueIo<W> {
+        QueueIo {
+            input: mem::take(&mut intcode_state.input),
+            output: mem::take(&mut intcode_state.output),
+        }
+    }
+
+    pub fn put_back(&mut self, mut intcode_state: IntcodeState<W>) -> IntcodeState<W> {
+        intcode_state.input = mem::take(&mut self.input);
+        intcode_state.output = mem::take(&mut self.output);
+        intcode_state
+    }
+}
+
+impl<W> IntcodeIo<W> for QueueIo<W> {
+    fn read(&mut self) -> Option<W> {
+        self.input.pop_front()
+    }
+
+    fn write(&mut self, value: W) {
+        self.output.push(value)
+    }
+}
+
+pub struct IterIo<I: Iterator> {
+    input: I,
+    pub output: Vec<I::Item>,
+}
+
+impl<I: Iterator> IterIo<I> {
+    pub fn new(input: impl IntoIterator<IntoIter = I, Item = I::Item>) -> IterIo<I> {
+        IterIo {
+            input: input.into_iter(),
+            output: vec![],
+        }
+    }
+}
+
+impl<I: Iterator> IntcodeIo<I::Item> for IterIo<I> {
+    fn read(&mut self) -> Option<I::Item> {
+        self.input.next()
+    }
+
+    fn write(&mut self, value: I::Item) {
+        self.output.push(value)
+    }
+}
+
+pub struct FnIo<R, F> {
+    reader: R,
+    writer: F,
+}
+
+pub fn from_fns<W, R, F>(reader: R, writer: F) -> FnIo<R, F>
+where
+    R: FnMut() -> Option<W>,
+    F: FnMut(W),
+{
+    FnIo { reader, writer }
+}
+
+impl<W, R, F> IntcodeIo<W> for FnIo<R, F>
+where
+    R: FnMut() -> Option<W>,
+    F: FnMut(W),
+{
+    fn read(&mut self) -> Option<W> {
+        (self.reader)()
+    }
+
+    fn write(&mut self, value: W) {
+        (self.writer)(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+    use crate::{run_with_io, IntcodeReturnType};
+
+    #[test]
+    fn test_iter_io() {
+        let mut io = IterIo::new(vec![3, 4]);
+        let return_type = run_with_io(IntcodeState::from(adder()), &mut io);
+
+        assert!(matches!(return_type, Ok(IntcodeReturnType::Finished(_))));
+        assert_eq!(io.output, vec![7]);
+    }
+
+    #[test]
+    fn test_fn_io() {
+        let mut next = 10;
+        let mut written = vec![];
+        let mut io = from_fns(
+            || {
+                next += 1;
+                Some(next)
+            },
+            |value| written.push(value),
+        );
+        run_with_io(IntcodeState::from(adder()), &mut io).unwrap();
+
+        assert_eq!(written, vec![23]);
+    }
+
+    #[test]
+    fn test_io_keeps_state_buffers_untouched() {
+        let mut io = IterIo::new(vec![1, 2]);
+        let return_type = run_with_io(IntcodeState::with_next_input(adder(), 5), &mut io);
+
+        if let Ok(IntcodeReturnType::Finished(state)) = return_type {
+            assert_eq!(state.input, vec![5]);
+            assert!(state.output.is_empty());
+        } else {
+            panic!("wrong enum variant {:?}", return_type)
+        }
+        assert_eq!(io.output, vec![3]);
+    }
+
+    #[test]
+    fn test_interrupt_when_io_is_exhausted() {
+        let mut io = IterIo::new(vec![1]);
+        let return_type = run_with_io(IntcodeState::from(adder()), &mut io);
+
+        if let Ok(IntcodeReturnType::Interrupted(state)) = return_type {
+            let mut io = IterIo::new(vec![2]);
+            run_with_io(state, &mut io).unwrap();
+            assert_eq!(io.output, vec![3]);
+        } else {
+            panic!("wrong enum variant {:?}", return_type)
+        }
+    }
+
+    #[test]
+    fn test_wide_words() {
+        let code: Vec<i128> = adder().into_iter().map(i128::from).collect();
+        let mut io = IterIo::new(vec![1 << 70, 1]);
+        run_with_io(IntcodeState::new(code.clone()), &mut io).unwrap();
+        assert_eq!(io.output, vec![(1 << 70) + 1]);
+
+        let mut written = vec![];
+        let mut io = from_fns(|| Some(1i128 << 80), |value| written.push(value));
+        run_with_io(IntcodeState::new(code), &mut io).unwrap();
+        assert_eq!(written, vec![1 << 81]);
+    }
+}
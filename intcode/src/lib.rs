@@ -1,324 +1,764 @@
-use crate::IntcodeReturnType::CodeError;
-use crate::ParamMode::{Immediate, Position};
-use crate::ProgramState::{Halted, Interrupted, Running};
+use crate::cycle::CycleDetector;
+use crate::history::History;
+use crate::io::{IntcodeIo, QueueIo};
+use crate::profiler::Profiler;
+use crate::registry::{Builtin, Role};
+use crate::trace::Tracer;
+use crate::word::{saturating_i64, Word};
+use crate::IntcodeErrorKind::*;
+use crate::ParamMode::{Immediate, Position, Relative};
+use crate::ProgramState::{Halted, Running};
+use std::collections::VecDeque;
 use std::convert::{TryFrom, TryInto};
 
+pub mod ascii;
+pub mod assembler;
+pub mod cache;
+pub mod cfg;
+pub mod cycle;
+pub mod debugger;
+pub mod device;
+pub mod disassembler;
+mod error;
+pub mod fuzz;
+pub mod history;
 pub mod input;
+pub mod io;
+pub mod machine;
+pub mod network;
+pub mod pipeline;
+pub mod profiler;
+pub mod registry;
+pub mod snapshot;
+#[cfg(test)]
+pub(crate) mod test_programs;
+pub mod threaded;
+pub mod trace;
+pub mod word;
+
+pub use crate::error::{IntcodeError, IntcodeErrorKind};
+
+#[derive(Debug, PartialEq)]
+pub enum IntcodeReturnType<W = i64> {
+    Finished(IntcodeState<W>),
+    Interrupted(IntcodeState<W>),
+    BudgetExhausted(IntcodeState<W>),
+}
+
+impl<W: Word> IntcodeReturnType<W> {
+    pub fn resume_with_input(self, input: W) -> IntcodeResult<W> {
+        match self {
+            IntcodeReturnType::Interrupted(mut state)
+            | IntcodeReturnType::BudgetExhausted(mut state) => {
+                state.input.push_back(input);
+                complete_intcode(state)
+            }
+            IntcodeReturnType::Finished(state) => {
+                Err(IntcodeError::at(&state, state.index, ResumeAfterHalt))
+            }
+        }
+    }
+
+    pub fn resume_with_budget(self, step_budget: u64) -> IntcodeResult<W> {
+        match self {
+            IntcodeReturnType::Interrupted(state) | IntcodeReturnType::BudgetExhausted(state) => {
+                complete_intcode(state.with_step_budget(step_budget))
+            }
+            IntcodeReturnType::Finished(state) => {
+                Err(IntcodeError::at(&state, state.index, ResumeAfterHalt))
+            }
+        }
+    }
+
+    fn new(outcome: Outcome, intcode_state: IntcodeState<W>) -> IntcodeReturnType<W> {
+        match outcome {
+            Outcome::Halted => IntcodeReturnType::Finished(intcode_state),
+            Outcome::WaitingForInput => IntcodeReturnType::Interrupted(intcode_state),
+            Outcome::BudgetExhausted => IntcodeReturnType::BudgetExhausted(intcode_state),
+            Outcome::Continue => unreachable!("a running machine has not returned"),
+        }
+    }
+
+    fn map_state(self, f: impl FnOnce(IntcodeState<W>) -> IntcodeState<W>) -> IntcodeReturnType<W> {
+        match self {
+            IntcodeReturnType::Finished(state) => IntcodeReturnType::Finished(f(state)),
+            IntcodeReturnType::Interrupted(state) => IntcodeReturnType::Interrupted(f(state)),
+            IntcodeReturnType::BudgetExhausted(state) => {
+                IntcodeReturnType::BudgetExhausted(f(state))
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Outcome {
+    Continue,
+    Halted,
+    WaitingForInput,
+    BudgetExhausted,
+}
 
 #[derive(Debug, PartialEq)]
-pub enum IntcodeReturnType {
-    CodeError,
-    IndexError,
-    Finished(IntcodeState),
-    Interrupted(IntcodeState),
+pub enum Stop<W = i64> {
+    Returned(IntcodeReturnType<W>),
+    Failed(IntcodeError),
 }
 
-impl IntcodeReturnType {
-    pub fn resume_with_input(self, input: i64) -> IntcodeReturnType {
-        if let IntcodeReturnType::Interrupted(mut state) = self {
-            state.input = input;
-            state.resume = true;
-            complete_intcode(state)
-        } else {
-            panic!("resume only on Interrupted state, used on {:?}", self)
+impl<W> Stop<W> {
+    fn into_result(self) -> IntcodeResult<W> {
+        match self {
+            Stop::Returned(return_type) => Ok(return_type),
+            Stop::Failed(error) => Err(error),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Default)]
-pub struct IntcodeState {
-    pub code: Memory,
+impl<W> From<IntcodeError> for Stop<W> {
+    fn from(error: IntcodeError) -> Self {
+        Stop::Failed(error)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct IntcodeState<W = i64> {
+    pub code: Memory<W>,
     index: usize,
-    pub input: i64,
-    pub output: Vec<i64>,
-    resume: bool,
+    pub input: VecDeque<W>,
+    pub output: Vec<W>,
+    relative_base: i64,
+    memory_limit: usize,
+    step_budget: Option<u64>,
+}
+
+pub const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+impl<W> Default for IntcodeState<W> {
+    fn default() -> Self {
+        IntcodeState {
+            code: Memory::default(),
+            index: 0,
+            input: VecDeque::new(),
+            output: vec![],
+            relative_base: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            step_budget: None,
+        }
+    }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum ProgramState {
     Running(OpMode),
     Halted,
-    Interrupted(OpMode),
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum ParamMode {
     Position,
     Immediate,
+    Relative,
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum OpMode {
-    Add(ParamMode, ParamMode),
-    Mul(ParamMode, ParamMode),
-    Input,
+    Add(ParamMode, ParamMode, ParamMode),
+    Mul(ParamMode, ParamMode, ParamMode),
+    Input(ParamMode),
     Output(ParamMode),
     JumpIfTrue(ParamMode, ParamMode),
     JumpIfFalse(ParamMode, ParamMode),
-    LessThan(ParamMode, ParamMode),
-    Equals(ParamMode, ParamMode),
+    LessThan(ParamMode, ParamMode, ParamMode),
+    Equals(ParamMode, ParamMode, ParamMode),
+    AdjustRelativeBase(ParamMode),
 }
 
-pub type Memory = Vec<i64>;
-pub type IntcodeResult = std::result::Result<IntcodeState, IntcodeReturnType>;
+pub type Memory<W = i64> = Vec<W>;
+pub type IntcodeResult<W = i64> = std::result::Result<IntcodeReturnType<W>, IntcodeError>;
+// The state is threaded by value through both sides of a step, so functions
+// returning this need `clippy::result_large_err` allowed
+pub type StepResult<W = i64> = std::result::Result<IntcodeState<W>, Stop<W>>;
 
 impl IntcodeState {
     pub fn from(code: Memory) -> IntcodeState {
+        IntcodeState::new(code)
+    }
+    pub fn with_next_input(code: Memory, input: i64) -> IntcodeState {
+        IntcodeState::with_inputs(code, vec![input])
+    }
+
+    pub fn with_inputs(code: Memory, inputs: Vec<i64>) -> IntcodeState {
         IntcodeState {
             code,
+            input: inputs.into(),
             ..IntcodeState::default()
         }
     }
-    pub fn with_next_input(code: Memory, input: i64) -> IntcodeState {
+
+    pub fn set_next_input(mut self, input: i64) -> IntcodeState {
+        self.input.push_back(input);
+        self
+    }
+}
+
+impl<W: Word> IntcodeState<W> {
+    pub fn new(code: Memory<W>) -> IntcodeState<W> {
         IntcodeState {
             code,
-            input,
-            resume: true,
             ..IntcodeState::default()
         }
     }
 
-    pub fn set_next_input(self, input: i64) -> IntcodeState {
+    pub fn with_memory_limit(self, memory_limit: usize) -> IntcodeState<W> {
         IntcodeState {
-            resume: true,
-            input,
+            memory_limit,
             ..self
         }
     }
+
+    pub fn with_step_budget(self, step_budget: u64) -> IntcodeState<W> {
+        IntcodeState {
+            step_budget: Some(step_budget),
+            ..self
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn step_budget(&self) -> Option<u64> {
+        self.step_budget
+    }
 }
 
 impl TryFrom<usize> for ParamMode {
-    type Error = IntcodeReturnType;
+    type Error = IntcodeErrorKind;
 
     fn try_from(value: usize) -> Result<Self, Self::Error> {
         match value {
             0 => Ok(Position),
             1 => Ok(Immediate),
-            _ => Err(CodeError),
+            2 => Ok(Relative),
+            _ => Err(UnknownParamMode(value as i64)),
         }
     }
 }
-trait TryToUsize {
-    fn to_usize(&self) -> Result<usize, IntcodeReturnType>;
+
+impl OpMode {
+    fn opcode(&self) -> usize {
+        use OpMode::*;
+
+        match self {
+            Add(..) => 1,
+            Mul(..) => 2,
+            Input(..) => 3,
+            Output(..) => 4,
+            JumpIfTrue(..) => 5,
+            JumpIfFalse(..) => 6,
+            LessThan(..) => 7,
+            Equals(..) => 8,
+            AdjustRelativeBase(..) => 9,
+        }
+    }
+
+    fn param_modes(&self) -> Vec<&ParamMode> {
+        use OpMode::*;
+
+        match self {
+            Add(mode_1, mode_2, mode_3)
+            | Mul(mode_1, mode_2, mode_3)
+            | LessThan(mode_1, mode_2, mode_3)
+            | Equals(mode_1, mode_2, mode_3) => vec![mode_1, mode_2, mode_3],
+            JumpIfTrue(mode_1, mode_2) | JumpIfFalse(mode_1, mode_2) => vec![mode_1, mode_2],
+            Input(mode) | Output(mode) | AdjustRelativeBase(mode) => vec![mode],
+        }
+    }
 }
 
-impl TryToUsize for i64 {
-    fn to_usize(&self) -> Result<usize, IntcodeReturnType> {
-        self.clone()
-            .try_into()
-            .map_err(|_| IntcodeReturnType::IndexError)
+impl ParamMode {
+    fn name(&self) -> &'static str {
+        match self {
+            Position => "position",
+            Immediate => "immediate",
+            Relative => "relative",
+        }
     }
 }
 
 impl ProgramState {
-    fn from_memory_location(input: i64) -> Result<Self, IntcodeReturnType> {
-        use OpMode::*;
+    fn opcode(&self) -> usize {
+        match self {
+            Running(op_mode) => op_mode.opcode(),
+            Halted => 99,
+        }
+    }
 
-        assert!(input <= 99999);
-        let mut n: usize = input.try_into().map_err(|_| IntcodeReturnType::CodeError)?;
-        let op_mode = n % 100;
-        n /= 100;
+    fn builtin(&self) -> &'static Builtin {
+        registry::builtin(self.opcode()).expect("every decoded instruction is built in")
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        self.builtin().mnemonic
+    }
 
-        let first_param = ParamMode::try_from(n % 10)?;
-        n /= 10;
-        let second_param = ParamMode::try_from(n % 10)?;
-        n /= 10;
-        let _third_param = ParamMode::try_from(n % 10)?;
+    fn param_modes(&self) -> Vec<&ParamMode> {
+        match self {
+            Running(op_mode) => op_mode.param_modes(),
+            Halted => vec![],
+        }
+    }
+
+    fn write_param(&self) -> Option<usize> {
+        self.builtin()
+            .roles
+            .iter()
+            .position(|role| *role == Role::Write)
+    }
+}
 
-        match op_mode {
-            1 => Ok(Running(Add(first_param, second_param))),
-            2 => Ok(Running(Mul(first_param, second_param))),
-            3 => Ok(Interrupted(Input)),
-            4 => Ok(Running(Output(first_param))),
-            5 => Ok(Running(JumpIfTrue(first_param, second_param))),
-            6 => Ok(Running(JumpIfFalse(first_param, second_param))),
-            7 => Ok(Running(LessThan(first_param, second_param))),
-            8 => Ok(Running(Equals(first_param, second_param))),
-            99 => Ok(Halted),
-            _ => Err(IntcodeReturnType::CodeError),
+// Splits an instruction into its opcode and the modes of up to three parameters
+fn split_instruction(input: i64) -> Result<(usize, [ParamMode; 3]), IntcodeError> {
+    if input < 0 {
+        return Err(IntcodeError::decoding(input, None, NegativeInstruction));
+    }
+    if input > 99999 {
+        return Err(IntcodeError::decoding(input, None, InstructionTooLong));
+    }
+    let mut n = input as usize;
+    let op_mode = n % 100;
+    n /= 100;
+
+    let param_mode = |parameter: usize, digit: usize| {
+        ParamMode::try_from(digit)
+            .map_err(|kind| IntcodeError::decoding(input, Some(parameter), kind))
+    };
+    let first_param = param_mode(1, n % 10)?;
+    n /= 10;
+    let second_param = param_mode(2, n % 10)?;
+    n /= 10;
+    let third_param = param_mode(3, n % 10)?;
+
+    Ok((op_mode, [first_param, second_param, third_param]))
+}
+
+impl ProgramState {
+    fn from_memory_location(input: i64) -> Result<Self, IntcodeError> {
+        let (opcode, modes) = split_instruction(input)?;
+
+        match registry::builtin(opcode) {
+            Some(builtin) => Ok(builtin.decode(modes)),
+            None => Err(IntcodeError::decoding(
+                input,
+                None,
+                UnknownOpcode(opcode as i64),
+            )),
         }
     }
 }
 
-pub fn run_instruction_set(memory: Memory) -> IntcodeReturnType {
+pub fn run_instruction_set(memory: Memory) -> IntcodeResult {
     complete_intcode(IntcodeState::from(memory))
 }
 
-pub fn run_instruction_set_with_input(memory: Memory, input: i64) -> IntcodeReturnType {
+pub fn run_instruction_set_with_input(memory: Memory, input: i64) -> IntcodeResult {
     complete_intcode(IntcodeState::with_next_input(memory, input))
 }
 
-fn complete_intcode(mut intcode_state: IntcodeState) -> IntcodeReturnType {
+pub fn run_instruction_set_with_inputs(memory: Memory, inputs: Vec<i64>) -> IntcodeResult {
+    complete_intcode(IntcodeState::with_inputs(memory, inputs))
+}
+
+pub fn run_with_io<W: Word>(
+    mut intcode_state: IntcodeState<W>,
+    io: &mut impl IntcodeIo<W>,
+) -> IntcodeResult<W> {
+    let outcome = run_in_place(&mut intcode_state, io)?;
+
+    Ok(IntcodeReturnType::new(outcome, intcode_state))
+}
+
+pub fn run_traced(mut intcode_state: IntcodeState, tracer: &mut impl Tracer) -> IntcodeResult {
+    let mut step = 0;
+    loop {
+        let (result, trace_step) = trace::traced_step(intcode_state, step);
+        if let Some(trace_step) = trace_step {
+            tracer.trace(&trace_step);
+        }
+        intcode_state = match result {
+            Ok(t) => t,
+            Err(stop) => return stop.into_result(),
+        };
+        step += 1;
+    }
+}
+
+pub fn run_recorded(mut intcode_state: IntcodeState, history: &mut History) -> IntcodeResult {
     loop {
+        intcode_state = match history.step(intcode_state) {
+            Ok(t) => t,
+            Err(stop) => return stop.into_result(),
+        };
+    }
+}
+
+pub fn run_profiled(mut intcode_state: IntcodeState, profiler: &mut Profiler) -> IntcodeResult {
+    loop {
+        intcode_state = match profiler.step(intcode_state) {
+            Ok(t) => t,
+            Err(stop) => return stop.into_result(),
+        };
+    }
+}
+
+pub fn run_detecting_cycles(mut intcode_state: IntcodeState) -> IntcodeResult {
+    let mut detector = CycleDetector::new();
+    loop {
+        if let Some(cycle_length) = detector.observe(&intcode_state) {
+            let index = intcode_state.index;
+            return Err(IntcodeError::at(
+                &intcode_state,
+                index,
+                InfiniteLoop { cycle_length },
+            ));
+        }
         intcode_state = match intcode_step(intcode_state) {
             Ok(t) => t,
-            Err(return_type) => return return_type,
+            Err(stop) => return stop.into_result(),
         };
     }
 }
 
-fn intcode_step(mut intcode_state: IntcodeState) -> IntcodeResult {
+// Runs a state with any word type until it halts or needs input
+pub fn run_intcode<W: Word>(intcode_state: IntcodeState<W>) -> IntcodeResult<W> {
+    complete_intcode(intcode_state)
+}
+
+fn complete_intcode<W: Word>(mut intcode_state: IntcodeState<W>) -> IntcodeResult<W> {
+    let mut io = QueueIo::take_from(&mut intcode_state);
+    let outcome = run_in_place(&mut intcode_state, &mut io);
+    let intcode_state = io.put_back(intcode_state);
+
+    Ok(IntcodeReturnType::new(outcome?, intcode_state))
+}
+
+#[allow(clippy::result_large_err)]
+pub fn intcode_step<W: Word>(mut intcode_state: IntcodeState<W>) -> StepResult<W> {
+    let mut io = QueueIo::take_from(&mut intcode_state);
+
+    match intcode_step_with_io(intcode_state, &mut io) {
+        Ok(state) => Ok(io.put_back(state)),
+        Err(Stop::Returned(return_type)) => Err(Stop::Returned(
+            return_type.map_state(|state| io.put_back(state)),
+        )),
+        Err(error) => Err(error),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn intcode_step_with_io<W: Word>(
+    mut intcode_state: IntcodeState<W>,
+    io: &mut dyn IntcodeIo<W>,
+) -> StepResult<W> {
+    match execute_step(&mut intcode_state, io)? {
+        Outcome::Continue => Ok(intcode_state),
+        outcome => Err(Stop::Returned(IntcodeReturnType::new(
+            outcome,
+            intcode_state,
+        ))),
+    }
+}
+
+fn run_in_place<W: Word>(
+    intcode_state: &mut IntcodeState<W>,
+    io: &mut dyn IntcodeIo<W>,
+) -> Result<Outcome, IntcodeError> {
+    loop {
+        match execute_step(intcode_state, io)? {
+            Outcome::Continue => {}
+            outcome => return Ok(outcome),
+        }
+    }
+}
+
+fn execute_step<W: Word>(
+    intcode_state: &mut IntcodeState<W>,
+    io: &mut dyn IntcodeIo<W>,
+) -> Result<Outcome, IntcodeError> {
+    if intcode_state.step_budget == Some(0) {
+        return Ok(Outcome::BudgetExhausted);
+    }
+
+    let program_state = decode(intcode_state)?;
+    let (outcome, _) = execute_decoded(intcode_state, program_state, io)?;
+
+    Ok(outcome)
+}
+
+fn decode<W: Word>(intcode_state: &IntcodeState<W>) -> Result<ProgramState, IntcodeError> {
     let index = intcode_state.index;
-    let instruction_field = get_index_value(&intcode_state.code, index)?;
+    let instruction_field = get_index_value(intcode_state, index)
+        .map_err(|kind| IntcodeError::at(intcode_state, index, kind))?;
+    let instruction_field = saturating_i64(&instruction_field);
 
-    let op_mode = match ProgramState::from_memory_location(instruction_field)? {
+    ProgramState::from_memory_location(instruction_field).map_err(|error| error.at_pc(index))
+}
+
+// Also returns the address the instruction wrote to, if any
+fn execute_decoded<W: Word>(
+    intcode_state: &mut IntcodeState<W>,
+    program_state: ProgramState,
+    io: &mut dyn IntcodeIo<W>,
+) -> Result<(Outcome, Option<usize>), IntcodeError> {
+    let op_mode = match program_state {
         Running(op_mode) => op_mode,
-        Halted => return Err(IntcodeReturnType::Finished(intcode_state)),
-        Interrupted(op_mode) => {
-            if intcode_state.resume {
-                intcode_state.resume = false;
-                op_mode
-            } else {
-                return Err(IntcodeReturnType::Interrupted(intcode_state));
-            }
-        }
+        Halted => return Ok((Outcome::Halted, None)),
     };
 
-    let new_state = process_op_mode(intcode_state, op_mode)?;
+    let (outcome, write) = process_op_mode(intcode_state, op_mode, io)?;
+    if outcome == Outcome::Continue {
+        if let Some(step_budget) = intcode_state.step_budget.as_mut() {
+            *step_budget -= 1;
+        }
+    }
 
-    Ok(new_state)
+    Ok((outcome, write))
 }
 
-fn process_op_mode(mut intcode_state: IntcodeState, op_mode: OpMode) -> IntcodeResult {
+fn process_op_mode<W: Word>(
+    intcode_state: &mut IntcodeState<W>,
+    op_mode: OpMode,
+    io: &mut dyn IntcodeIo<W>,
+) -> Result<(Outcome, Option<usize>), IntcodeError> {
     let index = intcode_state.index;
 
-    let new_state = match op_mode {
-        OpMode::Add(mode_1, mode_2) => {
-            op_modes_3_inputs(intcode_state, mode_1, mode_2, |a, b| a + b)?
-        }
-        OpMode::Mul(mode_1, mode_2) => {
-            op_modes_3_inputs(intcode_state, mode_1, mode_2, |a, b| a * b)?
-        }
-        OpMode::Input => {
-            intcode_state.code =
-                try_set_at_index_location(intcode_state.code, index + 1, intcode_state.input)?;
+    let write = match op_mode {
+        OpMode::Add(mode_1, mode_2, mode_3) => Some(op_modes_3_inputs(
+            intcode_state,
+            mode_1,
+            mode_2,
+            mode_3,
+            W::checked_add,
+        )?),
+        OpMode::Mul(mode_1, mode_2, mode_3) => Some(op_modes_3_inputs(
+            intcode_state,
+            mode_1,
+            mode_2,
+            mode_3,
+            W::checked_mul,
+        )?),
+        OpMode::Input(mode) => {
+            let target_index = get_target_index(intcode_state, index + 1, &mode)?;
+            let input = match io.read() {
+                Some(input) => input,
+                None => return Ok((Outcome::WaitingForInput, None)),
+            };
+            set_at_index_location(&mut intcode_state.code, target_index, input);
             intcode_state.index += 2;
 
-            intcode_state
+            Some(target_index)
         }
         OpMode::Output(mode) => {
-            let output = get_value_at_index_location(&intcode_state.code, index + 1, &mode)?;
+            let output = get_value_at_index_location(intcode_state, index + 1, &mode)?;
 
-            intcode_state.output.push(output);
+            io.write(output);
             intcode_state.index += 2;
 
-            intcode_state
+            None
         }
 
         OpMode::JumpIfTrue(mode_1, mode_2) => {
-            match get_value_at_index_location(&intcode_state.code, index + 1, &mode_1)? {
-                0 => intcode_state.index += 3,
-                _ => {
-                    let target =
-                        get_value_at_index_location(&intcode_state.code, index + 2, &mode_2)?;
-                    intcode_state.index = target.to_usize()?;
-                }
-            };
-            intcode_state
+            if get_value_at_index_location(intcode_state, index + 1, &mode_1)? == W::from(0) {
+                intcode_state.index += 3;
+            } else {
+                intcode_state.index = get_jump_target(intcode_state, index + 2, &mode_2)?;
+            }
+
+            None
         }
 
         OpMode::JumpIfFalse(mode_1, mode_2) => {
-            match get_value_at_index_location(&intcode_state.code, index + 1, &mode_1)? {
-                0 => {
-                    let target =
-                        get_value_at_index_location(&intcode_state.code, index + 2, &mode_2)?;
-                    intcode_state.index = target.to_usize()?;
-                }
-                _ => intcode_state.index += 3,
-            };
+            if get_value_at_index_location(intcode_state, index + 1, &mode_1)? == W::from(0) {
+                intcode_state.index = get_jump_target(intcode_state, index + 2, &mode_2)?;
+            } else {
+                intcode_state.index += 3;
+            }
 
-            intcode_state
+            None
         }
-        OpMode::LessThan(mode_1, mode_2) => op_modes_3_inputs(
+        OpMode::LessThan(mode_1, mode_2, mode_3) => Some(op_modes_3_inputs(
             intcode_state,
             mode_1,
             mode_2,
-            |a, b| if a < b { 1 } else { 0 },
-        )?,
-        OpMode::Equals(mode_1, mode_2) => op_modes_3_inputs(
+            mode_3,
+            |a, b| Some(W::from((a < b) as i64)),
+        )?),
+        OpMode::Equals(mode_1, mode_2, mode_3) => Some(op_modes_3_inputs(
             intcode_state,
             mode_1,
             mode_2,
-            |a, b| if a == b { 1 } else { 0 },
-        )?,
+            mode_3,
+            |a, b| Some(W::from((a == b) as i64)),
+        )?),
+        OpMode::AdjustRelativeBase(mode) => {
+            let adjustment = get_value_at_index_location(intcode_state, index + 1, &mode)?;
+            intcode_state.relative_base = adjustment
+                .to_i64()
+                .and_then(|adjustment| intcode_state.relative_base.checked_add(adjustment))
+                .ok_or_else(|| IntcodeError::at(intcode_state, index, Overflow))?;
+            intcode_state.index += 2;
+
+            None
+        }
     };
 
-    Ok(new_state)
+    Ok((Outcome::Continue, write))
 }
 
-fn op_modes_3_inputs(
-    mut intcode_state: IntcodeState,
+fn op_modes_3_inputs<W: Word>(
+    intcode_state: &mut IntcodeState<W>,
     mode_1: ParamMode,
     mode_2: ParamMode,
-    operation: impl Fn(i64, i64) -> i64,
-) -> IntcodeResult {
+    mode_3: ParamMode,
+    operation: impl Fn(&W, &W) -> Option<W>,
+) -> Result<usize, IntcodeError> {
     let index = intcode_state.index;
-    let operand_1 = get_value_at_index_location(&intcode_state.code, index + 1, &mode_1)?;
-    let operand_2 = get_value_at_index_location(&intcode_state.code, index + 2, &mode_2)?;
-
-    intcode_state.code = try_set_at_index_location(
-        intcode_state.code,
-        index + 3,
-        operation(operand_1, operand_2),
-    )?;
+    let operand_1 = get_value_at_index_location(intcode_state, index + 1, &mode_1)?;
+    let operand_2 = get_value_at_index_location(intcode_state, index + 2, &mode_2)?;
+
+    let target_index = get_target_index(intcode_state, index + 3, &mode_3)?;
+    let result = operation(&operand_1, &operand_2)
+        .ok_or_else(|| IntcodeError::at(intcode_state, index, Overflow))?;
+    set_at_index_location(&mut intcode_state.code, target_index, result);
     intcode_state.index += 4;
 
-    Ok(intcode_state)
+    Ok(target_index)
 }
 
-fn get_index_value(code: &Memory, index: usize) -> Result<i64, IntcodeReturnType> {
-    Ok(code
-        .get(index)
-        .ok_or(IntcodeReturnType::IndexError)?
-        .to_owned())
+fn get_index_value<W: Word>(
+    intcode_state: &IntcodeState<W>,
+    index: usize,
+) -> Result<W, IntcodeErrorKind> {
+    if index >= intcode_state.memory_limit {
+        return Err(MemoryLimitExceeded {
+            address: index,
+            limit: intcode_state.memory_limit,
+        });
+    }
+
+    Ok(read_memory(&intcode_state.code, index))
+}
+
+fn read_memory<W: Word>(code: &Memory<W>, index: usize) -> W {
+    code.get(index).cloned().unwrap_or_else(|| W::from(0))
+}
+
+fn get_target_index<W: Word>(
+    intcode_state: &IntcodeState<W>,
+    index: usize,
+    mode: &ParamMode,
+) -> Result<usize, IntcodeError> {
+    let error = |kind| IntcodeError::at(intcode_state, index, kind);
+
+    let index_value = get_index_value(intcode_state, index)
+        .map_err(error)?
+        .to_i64()
+        .ok_or_else(|| error(Overflow))?;
+    let address = match mode {
+        Position => index_value,
+        Relative => intcode_state
+            .relative_base
+            .checked_add(index_value)
+            .ok_or_else(|| error(Overflow))?,
+        Immediate => return Err(error(ImmediateWrite)),
+    };
+    let address: usize = address
+        .try_into()
+        .map_err(|_| error(NegativeAddress(address)))?;
+
+    if address >= intcode_state.memory_limit {
+        return Err(error(MemoryLimitExceeded {
+            address,
+            limit: intcode_state.memory_limit,
+        }));
+    }
+
+    Ok(address)
 }
 
-fn get_value_at_index_location(
-    code: &Memory,
+fn get_value_at_index_location<W: Word>(
+    intcode_state: &IntcodeState<W>,
     index: usize,
     mode: &ParamMode,
-) -> Result<i64, IntcodeReturnType> {
-    let index_value = get_index_value(code, index)?;
+) -> Result<W, IntcodeError> {
     match mode {
-        Immediate => Ok(index_value as i64),
-        Position => {
-            let i: usize = index_value.to_usize()?;
+        Immediate => get_index_value(intcode_state, index)
+            .map_err(|kind| IntcodeError::at(intcode_state, index, kind)),
+        Position | Relative => {
+            let i = get_target_index(intcode_state, index, mode)?;
 
-            Ok(code.get(i).ok_or(IntcodeReturnType::IndexError)?.to_owned())
+            Ok(read_memory(&intcode_state.code, i))
         }
     }
 }
 
-fn try_set_at_index_location(
-    mut code: Memory,
+fn get_jump_target<W: Word>(
+    intcode_state: &IntcodeState<W>,
     index: usize,
-    value: i64,
-) -> Result<Memory, IntcodeReturnType> {
-    let target_index: usize = code
-        .get(index)
-        .ok_or(IntcodeReturnType::IndexError)?
-        .to_owned()
-        .to_usize()?;
-    code.get(target_index)
-        .ok_or(IntcodeReturnType::IndexError)?;
-    code[target_index] = value;
+    mode: &ParamMode,
+) -> Result<usize, IntcodeError> {
+    let target = get_value_at_index_location(intcode_state, index, mode)?
+        .to_i64()
+        .ok_or_else(|| IntcodeError::at(intcode_state, index, Overflow))?;
+
+    target
+        .try_into()
+        .map_err(|_| IntcodeError::at(intcode_state, index, InvalidJumpTarget(target)))
+}
 
-    Ok(code)
+fn set_at_index_location<W: Word>(code: &mut Memory<W>, target_index: usize, value: W) {
+    if target_index >= code.len() {
+        code.resize(target_index + 1, W::from(0));
+    }
+    code[target_index] = value;
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_programs::adder;
 
     impl IntcodeState {
-        fn from_all(code: Memory, index: usize, input: i64, output: Vec<i64>) -> IntcodeState {
+        fn from_all(code: Memory, index: usize, input: Vec<i64>, output: Vec<i64>) -> IntcodeState {
             IntcodeState {
                 code,
                 index,
-                input,
+                input: input.into(),
                 output,
-                resume: false,
+                relative_base: 0,
+                memory_limit: DEFAULT_MEMORY_LIMIT,
+                step_budget: None,
             }
         }
     }
 
+    fn error(
+        pc: usize,
+        instruction: i64,
+        parameter: Option<usize>,
+        kind: IntcodeErrorKind,
+    ) -> IntcodeError {
+        IntcodeError {
+            pc,
+            instruction,
+            parameter,
+            kind,
+        }
+    }
+
     mod test_step {
         use super::*;
 
@@ -326,7 +766,7 @@ mod tests {
         fn test_intcode_step_add() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1, 0, 0, 0])),
-                Ok(IntcodeState::from_all(vec![2, 0, 0, 0], 4, 0, vec![]))
+                Ok(IntcodeState::from_all(vec![2, 0, 0, 0], 4, vec![], vec![]))
             );
         }
 
@@ -334,7 +774,7 @@ mod tests {
         fn test_intcode_step_mul() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![2, 0, 0, 0])),
-                Ok(IntcodeState::from_all(vec![4, 0, 0, 0], 4, 0, vec![]))
+                Ok(IntcodeState::from_all(vec![4, 0, 0, 0], 4, vec![], vec![]))
             );
         }
 
@@ -342,7 +782,7 @@ mod tests {
         fn test_intcode_step_add_2() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1, 0, 0, 3])),
-                Ok(IntcodeState::from_all(vec![1, 0, 0, 2], 4, 0, vec![]))
+                Ok(IntcodeState::from_all(vec![1, 0, 0, 2], 4, vec![], vec![]))
             );
         }
 
@@ -350,43 +790,93 @@ mod tests {
         fn test_intcode_step_mul_2() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![2, 0, 0, 2])),
-                Ok(IntcodeState::from_all(vec![2, 0, 4, 2], 4, 0, vec![]))
+                Ok(IntcodeState::from_all(vec![2, 0, 4, 2], 4, vec![], vec![]))
             );
         }
 
         #[test]
-        fn test_intcode_step_err_index_1() {
+        fn test_intcode_step_read_beyond_memory_1() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1, 5, 0, 1])),
-                Err(IntcodeReturnType::IndexError)
+                Ok(IntcodeState::from_all(vec![1, 1, 0, 1], 4, vec![], vec![]))
             );
         }
 
         #[test]
-        fn test_intcode_step_err_index_2() {
+        fn test_intcode_step_read_beyond_memory_2() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1, 0, 5, 1])),
-                Err(IntcodeReturnType::IndexError)
+                Ok(IntcodeState::from_all(vec![1, 1, 5, 1], 4, vec![], vec![]))
             );
         }
 
         #[test]
-        fn test_intcode_step_err_index_3() {
+        fn test_intcode_step_write_beyond_memory() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1, 0, 0, 5])),
-                Err(IntcodeReturnType::IndexError)
+                Ok(IntcodeState::from_all(
+                    vec![1, 0, 0, 5, 0, 2],
+                    4,
+                    vec![],
+                    vec![]
+                ))
             );
         }
 
         #[test]
-        fn test_intcode_return() {
+        fn test_intcode_step_err_index_negative_read() {
             assert_eq!(
-                intcode_step(IntcodeState::from(vec![99, 0, 0, 5])),
-                Err(IntcodeReturnType::Finished(IntcodeState::from_all(
-                    vec![99, 0, 0, 5],
+                intcode_step(IntcodeState::from(vec![1, -1, 0, 1])),
+                Err(Stop::Failed(error(0, 1, Some(1), NegativeAddress(-1))))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_index_negative_write() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![1, 0, 0, -5])),
+                Err(Stop::Failed(error(0, 1, Some(3), NegativeAddress(-5))))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_memory_limit_read() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![1, 8, 0, 1]).with_memory_limit(8)),
+                Err(Stop::Failed(error(
                     0,
+                    1,
+                    Some(1),
+                    MemoryLimitExceeded {
+                        address: 8,
+                        limit: 8
+                    }
+                )))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_memory_limit_write() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![1, 0, 0, 8]).with_memory_limit(8)),
+                Err(Stop::Failed(error(
                     0,
-                    vec![],
+                    1,
+                    Some(3),
+                    MemoryLimitExceeded {
+                        address: 8,
+                        limit: 8
+                    }
+                )))
+            );
+        }
+
+        #[test]
+        fn test_intcode_return() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![99, 0, 0, 5])),
+                Err(Stop::Returned(IntcodeReturnType::Finished(
+                    IntcodeState::from_all(vec![99, 0, 0, 5], 0, vec![], vec![],)
                 )))
             );
         }
@@ -395,21 +885,16 @@ mod tests {
         fn test_intcode_step_input() {
             assert_eq!(
                 intcode_step(IntcodeState::with_next_input(vec![3, 0], 5)),
-                Ok(IntcodeState::from_all(vec![5, 0], 2, 5, vec![]))
+                Ok(IntcodeState::from_all(vec![5, 0], 2, vec![], vec![]))
             );
         }
 
         #[test]
         fn test_intcode_step_input_continue() {
-            let mut intcode = IntcodeState::with_next_input(vec![3, 0], 5);
-            intcode.resume = false;
             assert_eq!(
-                intcode_step(intcode),
-                Err(IntcodeReturnType::Interrupted(IntcodeState::from_all(
-                    vec![3, 0],
-                    0,
-                    5,
-                    vec![]
+                intcode_step(IntcodeState::from(vec![3, 0])),
+                Err(Stop::Returned(IntcodeReturnType::Interrupted(
+                    IntcodeState::from_all(vec![3, 0], 0, vec![], vec![])
                 )))
             );
         }
@@ -418,12 +903,12 @@ mod tests {
         fn test_intcode_step_output() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![4, 1])),
-                Ok(IntcodeState::from_all(vec![4, 1], 2, 0, vec![1]))
+                Ok(IntcodeState::from_all(vec![4, 1], 2, vec![], vec![1]))
             );
 
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![4, 0])),
-                Ok(IntcodeState::from_all(vec![4, 0], 2, 0, vec![4]))
+                Ok(IntcodeState::from_all(vec![4, 0], 2, vec![], vec![4]))
             );
         }
 
@@ -434,7 +919,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1002, 4, 3, 4, 99],
                     4,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -447,7 +932,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1101, 100, -1, 4, 99],
                     4,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -457,7 +942,12 @@ mod tests {
         fn test_intcode_step_parameter_mode_add() {
             assert_eq!(
                 intcode_step(IntcodeState::from(vec![1101, 4, 3, 4, 33])),
-                Ok(IntcodeState::from_all(vec![1101, 4, 3, 4, 7], 4, 0, vec![]))
+                Ok(IntcodeState::from_all(
+                    vec![1101, 4, 3, 4, 7],
+                    4,
+                    vec![],
+                    vec![]
+                ))
             );
         }
 
@@ -468,7 +958,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1105, 1, 5, 4, 33],
                     5,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -481,7 +971,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1105, 0, 3, 4, 33],
                     3,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -494,7 +984,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1106, 1, 3, 4, 33],
                     3,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -507,7 +997,7 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![1106, 0, 5, 4, 33],
                     5,
-                    0,
+                    vec![],
                     vec![]
                 ))
             );
@@ -520,29 +1010,117 @@ mod tests {
                 Ok(IntcodeState::from_all(
                     vec![104, 55, 3, 4, 33],
                     2,
-                    0,
+                    vec![],
                     vec![55]
                 ))
             );
         }
+
+        #[test]
+        fn test_intcode_step_adjust_relative_base() {
+            let mut expected = IntcodeState::from_all(vec![109, 19], 2, vec![], vec![]);
+            expected.relative_base = 19;
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![109, 19])),
+                Ok(expected)
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_parameter_mode_relative_out() {
+            let mut intcode = IntcodeState::from(vec![204, -1, 7]);
+            intcode.relative_base = 3;
+            let mut expected = IntcodeState::from_all(vec![204, -1, 7], 2, vec![], vec![7]);
+            expected.relative_base = 3;
+            assert_eq!(intcode_step(intcode), Ok(expected));
+        }
+
+        #[test]
+        fn test_intcode_step_parameter_mode_relative_write() {
+            let mut intcode = IntcodeState::from(vec![21101, 2, 3, -2, 0]);
+            intcode.relative_base = 6;
+            let mut expected = IntcodeState::from_all(vec![21101, 2, 3, -2, 5], 4, vec![], vec![]);
+            expected.relative_base = 6;
+            assert_eq!(intcode_step(intcode), Ok(expected));
+        }
+
+        #[test]
+        fn test_intcode_step_err_immediate_write() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![11101, 2, 3, 0])),
+                Err(Stop::Failed(error(0, 11101, Some(3), ImmediateWrite)))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_param_mode() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![301, 2, 3, 0])),
+                Err(Stop::Failed(error(0, 301, Some(1), UnknownParamMode(3))))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_instruction_too_long() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![100001, 0, 0, 0])),
+                Err(Stop::Failed(error(0, 100001, None, InstructionTooLong)))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_negative_instruction() {
+            assert_eq!(
+                complete_intcode(IntcodeState::from(vec![1101, -100, 1, 4, 0])),
+                Err(error(4, -99, None, NegativeInstruction))
+            );
+        }
+
+        #[test]
+        fn test_intcode_step_err_jump_target() {
+            assert_eq!(
+                intcode_step(IntcodeState::from(vec![1105, 1, -1])),
+                Err(Stop::Failed(error(0, 1105, Some(2), InvalidJumpTarget(-1))))
+            );
+        }
     }
 
     mod test_complete {
         use super::*;
 
         #[test]
-        fn test_intcode_index_error_1() {
+        fn test_intcode_run_into_empty_memory() {
             assert_eq!(
                 complete_intcode(IntcodeState::from(vec![1, 0, 0, 0])),
-                IntcodeReturnType::IndexError
+                Err(error(4, 0, None, UnknownOpcode(0)))
             );
         }
 
         #[test]
-        fn test_intcode_index_error_2() {
+        fn test_intcode_index_error() {
             assert_eq!(
-                complete_intcode(IntcodeState::from(vec![1, 0, 0, 0, 1, 34, 4, 5])),
-                IntcodeReturnType::IndexError
+                complete_intcode(IntcodeState::from(vec![1, 0, 0, 0, 1, -34, 4, 5])),
+                Err(error(4, 1, Some(1), NegativeAddress(-34)))
+            );
+        }
+
+        #[test]
+        fn test_intcode_runaway_write() {
+            // Keeps writing to an ever growing relative address
+            assert_eq!(
+                complete_intcode(
+                    IntcodeState::from(vec![109, 1000, 21101, 1, 1, 0, 1105, 1, 0])
+                        .with_memory_limit(10_000)
+                ),
+                Err(error(
+                    2,
+                    21101,
+                    Some(3),
+                    MemoryLimitExceeded {
+                        address: 10_000,
+                        limit: 10_000
+                    }
+                ))
             );
         }
 
@@ -552,49 +1130,49 @@ mod tests {
                 complete_intcode(IntcodeState::from(vec![
                     1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50
                 ])),
-                IntcodeReturnType::Finished(IntcodeState::from_all(
+                Ok(IntcodeReturnType::Finished(IntcodeState::from_all(
                     vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
                     8,
-                    0,
                     vec![],
-                ))
+                    vec![],
+                )))
             );
 
             assert_eq!(
                 complete_intcode(IntcodeState::from(vec![1, 0, 0, 0, 99])),
-                IntcodeReturnType::Finished(IntcodeState::from_all(
+                Ok(IntcodeReturnType::Finished(IntcodeState::from_all(
                     vec![2, 0, 0, 0, 99],
                     4,
-                    0,
                     vec![],
-                ))
+                    vec![],
+                )))
             );
             assert_eq!(
                 complete_intcode(IntcodeState::from(vec![2, 3, 0, 3, 99])),
-                IntcodeReturnType::Finished(IntcodeState::from_all(
+                Ok(IntcodeReturnType::Finished(IntcodeState::from_all(
                     vec![2, 3, 0, 6, 99],
                     4,
-                    0,
                     vec![],
-                ))
+                    vec![],
+                )))
             );
             assert_eq!(
                 complete_intcode(IntcodeState::from(vec![2, 4, 4, 5, 99, 0])),
-                IntcodeReturnType::Finished(IntcodeState::from_all(
+                Ok(IntcodeReturnType::Finished(IntcodeState::from_all(
                     vec![2, 4, 4, 5, 99, 9801],
                     4,
-                    0,
                     vec![],
-                ))
+                    vec![],
+                )))
             );
             assert_eq!(
                 complete_intcode(IntcodeState::from(vec![1, 1, 1, 4, 99, 5, 6, 0, 99])),
-                IntcodeReturnType::Finished(IntcodeState::from_all(
+                Ok(IntcodeReturnType::Finished(IntcodeState::from_all(
                     vec![30, 1, 1, 4, 2, 5, 6, 0, 99],
                     8,
-                    0,
                     vec![],
-                ))
+                    vec![],
+                )))
             );
         }
 
@@ -693,11 +1271,102 @@ mod tests {
             );
         }
 
-        fn test_for_output(return_type: IntcodeReturnType, output: Vec<i64>) {
-            if let IntcodeReturnType::Finished(state) = return_type {
+        #[test]
+        fn test_intcodes_day9_large_numbers() {
+            test_for_output(
+                complete_intcode(IntcodeState::from(vec![104, 1125899906842624, 99])),
+                vec![1125899906842624],
+            );
+            test_for_output(
+                complete_intcode(IntcodeState::from(vec![
+                    1102, 34915192, 34915192, 7, 4, 7, 99, 0,
+                ])),
+                vec![1219070632396864],
+            );
+        }
+
+        #[test]
+        fn test_intcodes_input_queue() {
+            test_for_output(
+                complete_intcode(IntcodeState::with_inputs(adder(), vec![3, 4])),
+                vec![7],
+            );
+            test_for_output(
+                complete_intcode(IntcodeState::with_next_input(adder(), 3).set_next_input(5)),
+                vec![8],
+            );
+        }
+
+        #[test]
+        fn test_intcodes_interrupt_on_empty_queue() {
+            let interrupted = complete_intcode(IntcodeState::with_next_input(adder(), 3));
+            if let Ok(IntcodeReturnType::Interrupted(state)) = &interrupted {
+                assert_eq!(state.index, 2);
+                assert!(state.input.is_empty());
+            } else {
+                panic!("wrong enum variant {:?}", interrupted)
+            }
+
+            test_for_output(interrupted.unwrap().resume_with_input(6), vec![9]);
+        }
+
+        #[test]
+        fn test_intcodes_step_budget() {
+            // Counts down from 5 and halts
+            let input = || vec![1001, 8, -1, 8, 1005, 8, 0, 99, 5];
+
+            let exhausted = complete_intcode(IntcodeState::from(input()).with_step_budget(3));
+            if let Ok(IntcodeReturnType::BudgetExhausted(state)) = &exhausted {
+                assert_eq!(state.index, 4);
+                assert_eq!(state.code[8], 3);
+                assert_eq!(state.step_budget(), Some(0));
+            } else {
+                panic!("wrong enum variant {:?}", exhausted)
+            }
+
+            let finished = exhausted.unwrap().resume_with_budget(100);
+            if let Ok(IntcodeReturnType::Finished(state)) = &finished {
+                assert_eq!(state.code[8], 0);
+                assert_eq!(state.step_budget(), Some(93));
+            } else {
+                panic!("wrong enum variant {:?}", finished)
+            }
+        }
+
+        #[test]
+        fn test_intcodes_resume_after_halt() {
+            let finished = complete_intcode(IntcodeState::from(vec![1, 0, 0, 0, 99])).unwrap();
+
+            assert_eq!(
+                finished.resume_with_input(1),
+                Err(error(4, 99, None, ResumeAfterHalt))
+            );
+        }
+
+        #[test]
+        fn test_intcodes_day9_quine() {
+            let quine = vec![
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ];
+            test_for_output(complete_intcode(IntcodeState::from(quine.clone())), quine);
+        }
+
+        #[test]
+        fn test_intcodes_relative_input() {
+            // Reads the input relative to base 10 and echoes it back
+            let input = || vec![109, 10, 203, -1, 204, -1, 99, 0, 0, 0];
+
+            test_for_output(
+                complete_intcode(IntcodeState::with_next_input(input(), 42)),
+                vec![42],
+            );
+        }
+
+        fn test_for_output(result: IntcodeResult, output: Vec<i64>) {
+            if let Ok(IntcodeReturnType::Finished(state)) = result {
                 assert_eq!(state.output, output)
             } else {
-                assert!(false, format!("wrong enum variant {:?}", return_type))
+                panic!("wrong enum variant {:?}", result)
             }
         }
     }
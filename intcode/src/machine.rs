@@ -0,0 +1,238 @@
+use crate::ascii;
+use crate::cache::{execute_step_cached, DecodeCache};
+use crate::io::{IntcodeIo, QueueIo};
+use crate::profiler::{self, Profiler};
+use crate::registry::Registry;
+use crate::{execute_step, run_in_place, IntcodeError, IntcodeState, Memory, Outcome};
+
+// Runs a program in place and keeps its allocations around, so the same
+// machine can be reset and reused for many runs of a program
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Machine {
+    state: IntcodeState,
+    decode_cache: Option<DecodeCache>,
+}
+
+impl Machine {
+    pub fn new(program: &[i64]) -> Machine {
+        Machine::from(IntcodeState::from(program.to_vec()))
+    }
+
+    pub fn with_decode_cache(self) -> Machine {
+        Machine {
+            decode_cache: Some(DecodeCache::new()),
+            ..self
+        }
+    }
+
+    pub fn reset(&mut self, program: &[i64]) {
+        let state = &mut self.state;
+        state.code.clear();
+        state.code.extend_from_slice(program);
+        state.index = 0;
+        state.input.clear();
+        state.output.clear();
+        state.relative_base = 0;
+        state.step_budget = None;
+        self.clear_decode_cache();
+    }
+
+    pub fn state(&self) -> &IntcodeState {
+        &self.state
+    }
+
+    pub fn into_state(self) -> IntcodeState {
+        self.state
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.state.code
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        self.clear_decode_cache();
+        &mut self.state.code
+    }
+
+    pub fn push_input(&mut self, input: i64) {
+        self.state.input.push_back(input);
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        ascii::push_text(&mut self.state.input, text);
+    }
+
+    pub fn output(&self) -> &[i64] {
+        &self.state.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut Vec<i64> {
+        &mut self.state.output
+    }
+
+    pub fn last_output(&self) -> Option<i64> {
+        self.state.output.last().copied()
+    }
+
+    pub fn set_step_budget(&mut self, step_budget: Option<u64>) {
+        self.state.step_budget = step_budget;
+    }
+
+    pub fn step(&mut self) -> Result<Outcome, IntcodeError> {
+        let mut io = QueueIo::take_from(&mut self.state);
+        let result = self.step_with_io(&mut io);
+        self.put_back(io);
+
+        result
+    }
+
+    pub fn run(&mut self) -> Result<Outcome, IntcodeError> {
+        let mut io = QueueIo::take_from(&mut self.state);
+        let result = self.run_with_io(&mut io);
+        self.put_back(io);
+
+        result
+    }
+
+    pub fn run_with_io(&mut self, io: &mut impl IntcodeIo) -> Result<Outcome, IntcodeError> {
+        if self.decode_cache.is_none() {
+            return run_in_place(&mut self.state, io);
+        }
+
+        loop {
+            match self.step_with_io(io)? {
+                Outcome::Continue => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    pub fn run_profiled(&mut self, profiler: &mut Profiler) -> Result<Outcome, IntcodeError> {
+        let mut io = QueueIo::take_from(&mut self.state);
+        let result = loop {
+            let access = profiler::access(&self.state);
+            let result = self.step_with_io(&mut io);
+            if let (Ok(Outcome::Continue), Some(access)) | (Ok(Outcome::Halted), Some(access)) =
+                (&result, access)
+            {
+                profiler.record(access);
+            }
+            if result != Ok(Outcome::Continue) {
+                break result;
+            }
+        };
+        self.put_back(io);
+
+        result
+    }
+
+    // Runs with the instructions of `registry` in place of the built-in ones.
+    // Its writes bypass the decode cache, so the cache starts over afterwards
+    pub fn run_with_registry(&mut self, registry: &Registry) -> Result<Outcome, IntcodeError> {
+        let mut io = QueueIo::take_from(&mut self.state);
+        let result = registry.run_in_place(&mut self.state, &mut io);
+        self.put_back(io);
+        self.clear_decode_cache();
+
+        result
+    }
+
+    fn step_with_io(&mut self, io: &mut dyn IntcodeIo) -> Result<Outcome, IntcodeError> {
+        match &mut self.decode_cache {
+            Some(cache) => execute_step_cached(&mut self.state, io, cache),
+            None => execute_step(&mut self.state, io),
+        }
+    }
+
+    fn put_back(&mut self, io: QueueIo) {
+        self.state.input = io.input;
+        self.state.output = io.output;
+    }
+
+    fn clear_decode_cache(&mut self) {
+        if let Some(cache) = &mut self.decode_cache {
+            cache.clear();
+        }
+    }
+}
+
+impl From<IntcodeState> for Machine {
+    fn from(state: IntcodeState) -> Self {
+        Machine {
+            state,
+            decode_cache: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+    use crate::{run_instruction_set_with_inputs, IntcodeErrorKind, IntcodeReturnType};
+
+    #[test]
+    fn test_run_and_reuse() {
+        let mut machine = Machine::new(&adder());
+        assert_eq!(machine.run(), Ok(Outcome::WaitingForInput));
+
+        machine.push_input(3);
+        machine.push_input(4);
+        assert_eq!(machine.run(), Ok(Outcome::Halted));
+        assert_eq!(machine.output(), &[7]);
+
+        machine.reset(&adder());
+        machine.push_input(10);
+        machine.push_input(20);
+        assert_eq!(machine.run(), Ok(Outcome::Halted));
+        assert_eq!(machine.last_output(), Some(30));
+        assert_eq!(machine.memory()[13], 30);
+    }
+
+    #[test]
+    fn test_matches_by_value_run() {
+        let mut machine = Machine::new(&adder());
+        machine.push_input(5);
+        machine.push_input(6);
+        machine.run().unwrap();
+
+        assert_eq!(
+            run_instruction_set_with_inputs(adder(), vec![5, 6]),
+            Ok(IntcodeReturnType::Finished(machine.into_state()))
+        );
+    }
+
+    #[test]
+    fn test_step_budget_and_errors() {
+        let mut machine = Machine::new(&[1101, 1, 1, 5, 1105, 1, 0]);
+        machine.set_step_budget(Some(3));
+        assert_eq!(machine.step(), Ok(Outcome::Continue));
+        assert_eq!(machine.run(), Ok(Outcome::BudgetExhausted));
+        assert_eq!(machine.state().index(), 4);
+
+        machine.reset(&[1, -1, 0, 0]);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, IntcodeErrorKind::NegativeAddress(-1));
+        assert_eq!(machine.state().index(), 0);
+    }
+
+    #[test]
+    fn test_registry_run_invalidates_decode_cache() {
+        // Overwrites its first instruction with a halt and jumps back to it
+        let code = [3, 20, 1101, 99, 0, 0, 1105, 1, 0];
+        let mut machine = Machine::new(&code).with_decode_cache();
+        assert_eq!(machine.run(), Ok(Outcome::WaitingForInput));
+
+        machine.push_input(5);
+        machine.set_step_budget(Some(2));
+        assert_eq!(
+            machine.run_with_registry(&Registry::builtin()),
+            Ok(Outcome::BudgetExhausted)
+        );
+        assert_eq!(machine.memory()[0], 99);
+
+        machine.set_step_budget(None);
+        assert_eq!(machine.run(), Ok(Outcome::Halted));
+        assert_eq!(machine.state().index(), 0);
+    }
+}
@@ -0,0 +1,151 @@
+use intcode::ascii::{Terminal, TERMINAL_HELP};
+use intcode::machine::Machine;
+use intcode::profiler::Profiler;
+use intcode::trace::JsonLinesTracer;
+use intcode::{assembler, cfg, disassembler, fuzz, input, run_profiled, run_traced, IntcodeState};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "\
+usage: intcode disasm <file>
+       intcode cfg <file> [entries...]
+       intcode asm <file>
+       intcode trace <file> <trace-file> [inputs...]
+       intcode profile <file> [inputs...]
+       intcode fuzz [seed] [cases]
+       intcode ascii <file>";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["disasm", file] => {
+            let code = input::get_memory_from_file(file);
+            print!("{}", disassembler::listing(&code));
+        }
+        ["cfg", file, entries @ ..] => {
+            let code = input::get_memory_from_file(file);
+            let mut entries: Vec<usize> = entries
+                .iter()
+                .map(|entry| entry.parse().expect("entries have to be addresses"))
+                .collect();
+            entries.insert(0, 0);
+            print!("{}", cfg::extract_from(&code, &entries).to_dot());
+        }
+        ["asm", file] => {
+            let source = fs::read_to_string(file).expect("Something went wrong reading the file");
+            match assembler::assemble(&source) {
+                Ok(code) => {
+                    let words: Vec<String> = code.iter().map(i64::to_string).collect();
+                    println!("{}", words.join(","));
+                }
+                Err(error) => {
+                    eprintln!("{}: {}", file, error);
+                    process::exit(1);
+                }
+            }
+        }
+        ["trace", file, trace_file, inputs @ ..] => {
+            let inputs = inputs
+                .iter()
+                .map(|input| input.parse().expect("inputs have to be numbers"))
+                .collect();
+            let state = IntcodeState::with_inputs(input::get_memory_from_file(file), inputs);
+            let writer = BufWriter::new(File::create(trace_file).expect("could not create trace"));
+
+            let mut tracer = JsonLinesTracer::new(writer);
+            let result = run_traced(state, &mut tracer);
+            tracer.finish().expect("could not write trace");
+
+            match result {
+                Ok(return_type) => println!("Intcode Return: {:?}", return_type),
+                Err(error) => {
+                    eprintln!("error: {}", error);
+                    process::exit(1);
+                }
+            }
+        }
+        ["profile", file, inputs @ ..] => {
+            let inputs = inputs
+                .iter()
+                .map(|input| input.parse().expect("inputs have to be numbers"))
+                .collect();
+            let code = input::get_memory_from_file(file);
+            let mut profiler = Profiler::new();
+
+            if let Err(error) = run_profiled(
+                IntcodeState::with_inputs(code.clone(), inputs),
+                &mut profiler,
+            ) {
+                eprintln!("error: {}", error);
+            }
+            print!("{}", profiler.report(&code, 10));
+        }
+        ["fuzz", options @ ..] if options.len() <= 2 => {
+            let seed = options.first().map_or(fuzz::DEFAULT_SEED, |seed| {
+                seed.parse().expect("the seed has to be a number")
+            });
+            let cases = options.get(1).map_or(10_000, |cases| {
+                cases
+                    .parse()
+                    .expect("the number of cases has to be a number")
+            });
+            for (name, backend) in fuzz::BACKENDS.iter() {
+                match fuzz::Fuzzer::new(seed, cases).run(backend) {
+                    Ok(()) => println!("{}: {} cases agree", name, cases),
+                    Err(failure) => {
+                        eprintln!("{}: {}", name, failure);
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        ["ascii", file] => {
+            let machine = Machine::new(&input::get_memory_from_file(file));
+            run_terminal(Terminal::new(machine));
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_terminal(mut terminal: Terminal) {
+    println!("{}", TERMINAL_HELP);
+    print_reply(terminal.run());
+
+    let stdin = io::stdin();
+    while !terminal.is_halted() {
+        io::stdout().flush().expect("could not write to stdout");
+
+        let mut line = String::new();
+        if stdin
+            .lock()
+            .read_line(&mut line)
+            .expect("could not read from stdin")
+            == 0
+        {
+            break;
+        }
+        match line.trim_end_matches(&['\r', '\n'][..]) {
+            ":quit" => break,
+            line => print_reply(terminal.execute(line)),
+        }
+    }
+}
+
+fn print_reply(reply: Result<String, String>) {
+    match reply {
+        Ok(text) if text.is_empty() => {}
+        Ok(text) => println!("{}", text),
+        Err(message) => eprintln!("error: {}", message),
+    }
+}
@@ -0,0 +1,372 @@
+use crate::io::IntcodeIo;
+use crate::machine::Machine;
+use crate::{IntcodeError, Outcome};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+pub const NAT_ADDRESS: i64 = 255;
+pub const DEFAULT_TIME_SLICE: u64 = 1000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Packet {
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Round {
+    pub packets: Vec<Packet>,
+    pub idle: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    Failed { address: usize, error: IntcodeError },
+    AllHalted,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Failed { address, error } => write!(f, "machine {}: {}", address, error),
+            NetworkError::AllHalted => write!(f, "every machine has halted"),
+        }
+    }
+}
+
+impl Error for NetworkError {}
+
+struct Node {
+    machine: Machine,
+    input: VecDeque<i64>,
+    halted: bool,
+}
+
+// Reading from an empty queue never blocks, it reads -1
+struct NodeIo<'a> {
+    input: &'a mut VecDeque<i64>,
+    output: Vec<i64>,
+    received: usize,
+    empty_reads: usize,
+}
+
+impl IntcodeIo for NodeIo<'_> {
+    fn read(&mut self) -> Option<i64> {
+        match self.input.pop_front() {
+            Some(value) => {
+                self.received += 1;
+                Some(value)
+            }
+            None => {
+                self.empty_reads += 1;
+                Some(-1)
+            }
+        }
+    }
+
+    fn write(&mut self, value: i64) {
+        self.output.push(value)
+    }
+}
+
+// Runs every machine in address order for at most one time slice per round.
+// A machine that reads from its empty input queue gets -1 right away
+pub struct Network {
+    nodes: Vec<Node>,
+    time_slice: u64,
+}
+
+impl Network {
+    pub fn new(program: &[i64], size: usize) -> Network {
+        let nodes = (0..size)
+            .map(|address| Node {
+                machine: Machine::new(program),
+                input: vec![address as i64].into(),
+                halted: false,
+            })
+            .collect();
+
+        Network {
+            nodes,
+            time_slice: DEFAULT_TIME_SLICE,
+        }
+    }
+
+    pub fn with_time_slice(self, time_slice: u64) -> Network {
+        Network { time_slice, ..self }
+    }
+
+    pub fn size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn machine(&self, address: usize) -> &Machine {
+        &self.nodes[address].machine
+    }
+
+    pub fn send(&mut self, packet: Packet) -> bool {
+        let node = match usize::try_from(packet.destination)
+            .ok()
+            .and_then(|address| self.nodes.get_mut(address))
+        {
+            Some(node) => node,
+            None => return false,
+        };
+
+        node.input.push_back(packet.x);
+        node.input.push_back(packet.y);
+        true
+    }
+
+    // The network is idle after a round in which no machine received or sent
+    // anything and every machine that has not halted read from an empty queue
+    pub fn round(&mut self) -> Result<Round, NetworkError> {
+        if self.nodes.iter().all(|node| node.halted) {
+            return Err(NetworkError::AllHalted);
+        }
+        let mut round = Round {
+            packets: vec![],
+            idle: true,
+        };
+
+        for address in 0..self.nodes.len() {
+            let node = &mut self.nodes[address];
+            if node.halted {
+                continue;
+            }
+
+            let mut io = NodeIo {
+                input: &mut node.input,
+                output: vec![],
+                received: 0,
+                empty_reads: 0,
+            };
+            node.machine.set_step_budget(Some(self.time_slice));
+            let outcome = node
+                .machine
+                .run_with_io(&mut io)
+                .map_err(|error| NetworkError::Failed { address, error })?;
+            node.halted = outcome == Outcome::Halted;
+
+            let output = node.machine.output_mut();
+            output.append(&mut io.output);
+            let complete = output.len() - output.len() % 3;
+            let packets: Vec<Packet> = output
+                .drain(..complete)
+                .collect::<Vec<_>>()
+                .chunks(3)
+                .map(|words| Packet {
+                    destination: words[0],
+                    x: words[1],
+                    y: words[2],
+                })
+                .collect();
+
+            round.idle &=
+                !node.halted && io.empty_reads > 0 && io.received == 0 && packets.is_empty();
+            for packet in packets {
+                self.send(packet);
+                round.packets.push(packet);
+            }
+        }
+        round.idle &= self.nodes.iter().any(|node| !node.halted);
+
+        Ok(round)
+    }
+}
+
+// Keeps the last packet sent to `NAT_ADDRESS` and sends it to machine 0
+// whenever a round ends with the whole network idle
+#[derive(Debug, Default)]
+pub struct Nat {
+    last_packet: Option<Packet>,
+}
+
+impl Nat {
+    pub fn new() -> Nat {
+        Nat::default()
+    }
+
+    pub fn last_packet(&self) -> Option<Packet> {
+        self.last_packet
+    }
+
+    pub fn monitor(&mut self, network: &mut Network, round: &Round) -> Option<Packet> {
+        if let Some(packet) = round
+            .packets
+            .iter()
+            .rev()
+            .find(|packet| packet.destination == NAT_ADDRESS)
+        {
+            self.last_packet = Some(*packet);
+        }
+        if !round.idle {
+            return None;
+        }
+
+        let packet = Packet {
+            destination: 0,
+            ..self.last_packet?
+        };
+        network.send(packet);
+
+        Some(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    // Forwards every packet to the next address with x incremented, the last
+    // machine of three sends to the NAT
+    fn relay() -> Vec<i64> {
+        assemble(
+            "
+                    in [address]
+            loop:   in [x]
+                    eq [x], #-1, [flag]
+                    jnz [flag], #loop
+                    in [y]
+                    add [address], #1, [destination]
+                    lt [destination], #3, [flag]
+                    jnz [flag], #send
+                    add #255, #0, [destination]
+            send:   out [destination]
+                    add [x], #1, [x]
+                    out [x]
+                    out [y]
+                    jz #0, #loop
+            address: data 0
+            x:      data 0
+            y:      data 0
+            destination: data 0
+            flag:   data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_routing_and_idle() {
+        let mut network = Network::new(&relay(), 3);
+
+        let round = network.round().unwrap();
+        assert_eq!(round.packets, vec![]);
+        assert!(!round.idle);
+        assert!(network.round().unwrap().idle);
+
+        assert!(network.send(Packet {
+            destination: 0,
+            x: 1,
+            y: 100
+        }));
+        let round = network.round().unwrap();
+        assert_eq!(
+            round.packets,
+            vec![
+                Packet {
+                    destination: 1,
+                    x: 2,
+                    y: 100
+                },
+                Packet {
+                    destination: 2,
+                    x: 3,
+                    y: 100
+                },
+                Packet {
+                    destination: NAT_ADDRESS,
+                    x: 4,
+                    y: 100
+                },
+            ]
+        );
+        assert!(!round.idle);
+        assert!(network.round().unwrap().idle);
+        assert!(!network.send(Packet {
+            destination: 3,
+            x: 0,
+            y: 0
+        }));
+    }
+
+    fn nat_deliveries(rounds: usize) -> Vec<Packet> {
+        let mut network = Network::new(&relay(), 3);
+        let mut nat = Nat::new();
+        network.send(Packet {
+            destination: 0,
+            x: 1,
+            y: 100,
+        });
+
+        let mut deliveries = vec![];
+        for _ in 0..rounds {
+            let round = network.round().unwrap();
+            deliveries.extend(nat.monitor(&mut network, &round));
+        }
+        deliveries
+    }
+
+    #[test]
+    fn test_nat_wakes_the_network() {
+        let deliveries = nat_deliveries(8);
+
+        assert_eq!(
+            deliveries.iter().map(|packet| packet.x).collect::<Vec<_>>(),
+            vec![4, 7, 10, 13]
+        );
+        assert!(deliveries.iter().all(|packet| packet.destination == 0));
+        assert_eq!(nat_deliveries(8), deliveries);
+    }
+
+    #[test]
+    fn test_time_slice_and_errors() {
+        // Busy loops forever without reading input
+        let mut network = Network::new(&[3, 100, 1105, 1, 2], 2).with_time_slice(10);
+        let round = network.round().unwrap();
+        assert!(!round.idle);
+        assert_eq!(network.machine(1).state().index(), 2);
+
+        let mut network = Network::new(&[3, 100, 1, -1, 0, 0], 2);
+        match network.round() {
+            Err(NetworkError::Failed { address, .. }) => assert_eq!(address, 0),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_reads_and_halted_machines() {
+        // Reads its address and three more values, outputs them and halts
+        let mut network = Network::new(&[3, 20, 3, 21, 3, 22, 4, 21, 4, 22, 4, 20, 99], 2);
+        network.send(Packet {
+            destination: 1,
+            x: 7,
+            y: 8,
+        });
+
+        // Machine 0 reads -1 twice within its first turn instead of blocking
+        let round = network.round().unwrap();
+        assert_eq!(
+            round.packets,
+            vec![
+                Packet {
+                    destination: -1,
+                    x: -1,
+                    y: 0
+                },
+                Packet {
+                    destination: 7,
+                    x: 8,
+                    y: 1
+                }
+            ]
+        );
+        assert!(!round.idle);
+        assert_eq!(network.round(), Err(NetworkError::AllHalted));
+    }
+}
@@ -0,0 +1,298 @@
+use crate::machine::Machine;
+use crate::profiler::Profiler;
+use crate::threaded::{ChannelIo, Supervisor};
+use crate::{IntcodeError, Outcome};
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::channel;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PipelineOutput {
+    pub signal: i64,
+    pub outputs: Vec<Vec<i64>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PipelineError {
+    Failed { machine: usize, error: IntcodeError },
+    Deadlock,
+    NoSignal,
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::Failed { machine, error } => write!(f, "machine {}: {}", machine, error),
+            PipelineError::Deadlock => write!(f, "all machines are waiting for input"),
+            PipelineError::NoSignal => write!(f, "the last machine halted without output"),
+        }
+    }
+}
+
+impl Error for PipelineError {}
+
+// Every output of a machine is fed to the next one in the chain. With
+// feedback the last machine feeds the first one again
+pub struct Pipeline {
+    machines: Vec<Machine>,
+    feedback: bool,
+}
+
+impl Pipeline {
+    pub fn new<I>(program: &[i64], initial_inputs: I) -> Pipeline
+    where
+        I: IntoIterator<Item = Vec<i64>>,
+    {
+        let machines = initial_inputs
+            .into_iter()
+            .map(|inputs| {
+                let mut machine = Machine::new(program);
+                inputs
+                    .into_iter()
+                    .for_each(|input| machine.push_input(input));
+                machine
+            })
+            .collect();
+
+        Pipeline {
+            machines,
+            feedback: false,
+        }
+    }
+
+    pub fn with_feedback(self) -> Pipeline {
+        Pipeline {
+            feedback: true,
+            ..self
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    // Feeds `signal` to the first machine and runs until the last one halts
+    pub fn run(self, signal: i64) -> Result<PipelineOutput, PipelineError> {
+        self.run_with(signal, None)
+    }
+
+    // Runs like `run`, all machines share the one profiler
+    pub fn run_profiled(
+        self,
+        signal: i64,
+        profiler: &mut Profiler,
+    ) -> Result<PipelineOutput, PipelineError> {
+        self.run_with(signal, Some(profiler))
+    }
+
+    fn run_with(
+        mut self,
+        signal: i64,
+        mut profiler: Option<&mut Profiler>,
+    ) -> Result<PipelineOutput, PipelineError> {
+        let count = self.machines.len();
+        if count == 0 {
+            return Err(PipelineError::NoSignal);
+        }
+
+        let mut forwarded = vec![0; count];
+        let mut halted = vec![false; count];
+        self.machines[0].push_input(signal);
+
+        while !halted[count - 1] {
+            let mut progress = false;
+
+            for index in 0..count {
+                if halted[index] {
+                    continue;
+                }
+
+                let machine = &mut self.machines[index];
+                let outcome = match profiler.as_deref_mut() {
+                    Some(profiler) => machine.run_profiled(profiler),
+                    None => machine.run(),
+                }
+                .map_err(|error| PipelineError::Failed {
+                    machine: index,
+                    error,
+                })?;
+                halted[index] = outcome == Outcome::Halted;
+
+                let new_output = machine.output()[forwarded[index]..].to_vec();
+                forwarded[index] = machine.output().len();
+                progress |= halted[index] || !new_output.is_empty();
+
+                let next = if index + 1 < count {
+                    index + 1
+                } else if self.feedback {
+                    0
+                } else {
+                    continue;
+                };
+                new_output
+                    .into_iter()
+                    .for_each(|value| self.machines[next].push_input(value));
+            }
+
+            if !progress && !halted[count - 1] {
+                return Err(PipelineError::Deadlock);
+            }
+        }
+
+        let outputs = self
+            .machines
+            .into_iter()
+            .map(|machine| machine.into_state().output)
+            .collect();
+
+        PipelineOutput::from_outputs(outputs)
+    }
+
+    // Runs every machine on its own thread, connected by channels. A machine
+    // whose upstream is gone stops waiting for input, and a feedback loop in
+    // which every machine waits is reported as a deadlock
+    pub fn run_threaded(self, signal: i64) -> Result<PipelineOutput, PipelineError> {
+        let count = self.machines.len();
+        if count == 0 {
+            return Err(PipelineError::NoSignal);
+        }
+
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..count).map(|_| channel()).unzip();
+        senders[0].send(signal).unwrap();
+
+        let mut supervisor = Supervisor::detecting_deadlocks();
+        for (index, (machine, input)) in self.machines.into_iter().zip(receivers).enumerate() {
+            let output = if index + 1 < count {
+                senders[index + 1].clone()
+            } else if self.feedback {
+                senders[0].clone()
+            } else {
+                channel().0
+            };
+            let io = ChannelIo::new(input, output);
+            supervisor.spawn(machine.into_state(), io);
+        }
+        drop(senders);
+
+        let mut outputs = vec![];
+        for (machine, report) in supervisor.join().into_iter().enumerate() {
+            match report.result {
+                Ok(outcome) if machine == count - 1 && outcome != Outcome::Halted => {
+                    return Err(PipelineError::Deadlock)
+                }
+                Ok(_) => outputs.push(report.state.output),
+                Err(error) => return Err(PipelineError::Failed { machine, error }),
+            }
+        }
+
+        PipelineOutput::from_outputs(outputs)
+    }
+}
+
+impl PipelineOutput {
+    fn from_outputs(outputs: Vec<Vec<i64>>) -> Result<PipelineOutput, PipelineError> {
+        Ok(PipelineOutput {
+            signal: *outputs
+                .last()
+                .and_then(|output| output.last())
+                .ok_or(PipelineError::NoSignal)?,
+            outputs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+
+    // Reads a count, then keeps doubling its input and halts after `count` outputs
+    fn doubler() -> Vec<i64> {
+        vec![
+            3, 20, 3, 21, 1002, 21, 2, 21, 4, 21, 1001, 20, -1, 20, 1005, 20, 2, 99, 0, 0, 0, 0,
+        ]
+    }
+
+    #[test]
+    fn test_chain() {
+        let output = Pipeline::new(&adder(), vec![vec![1], vec![10], vec![100]])
+            .run(0)
+            .unwrap();
+
+        assert_eq!(output.signal, 111);
+        assert_eq!(output.outputs, vec![vec![1], vec![11], vec![111]]);
+    }
+
+    #[test]
+    fn test_feedback_loop() {
+        let output = Pipeline::new(&doubler(), vec![vec![3], vec![3]])
+            .with_feedback()
+            .run(1)
+            .unwrap();
+
+        assert_eq!(output.signal, 64);
+        assert_eq!(output.outputs, vec![vec![2, 8, 32], vec![4, 16, 64]]);
+    }
+
+    #[test]
+    fn test_profiled_run() {
+        let mut profiler = Profiler::new();
+        let output = Pipeline::new(&doubler(), vec![vec![3], vec![3]])
+            .with_feedback()
+            .run_profiled(1, &mut profiler)
+            .unwrap();
+
+        assert_eq!(output.signal, 64);
+        assert_eq!(profiler.executions(0), 2);
+        assert_eq!(profiler.executions(2), 6);
+        assert!(profiler.coverage(&doubler()).is_complete());
+    }
+
+    #[test]
+    fn test_threaded_matches_sequential() {
+        let chain = || Pipeline::new(&adder(), vec![vec![1], vec![10], vec![100]]);
+        assert_eq!(chain().run_threaded(0), chain().run(0));
+
+        let feedback = || Pipeline::new(&doubler(), vec![vec![3], vec![3]]).with_feedback();
+        assert_eq!(feedback().run_threaded(1), feedback().run(1));
+    }
+
+    #[test]
+    fn test_errors() {
+        let pipeline = Pipeline::new(&adder(), vec![vec![], vec![]]);
+        assert_eq!(pipeline.run(1), Err(PipelineError::Deadlock));
+
+        let pipeline = Pipeline::new(&[99], vec![vec![]]);
+        assert_eq!(pipeline.run(0), Err(PipelineError::NoSignal));
+
+        let pipeline = Pipeline::new(&adder(), vec![vec![], vec![]]);
+        assert_eq!(pipeline.run_threaded(1), Err(PipelineError::Deadlock));
+
+        for threaded in [false, true].iter() {
+            let pipeline = Pipeline::new(&adder(), vec![vec![], vec![]]).with_feedback();
+            let result = if *threaded {
+                pipeline.run_threaded(1)
+            } else {
+                pipeline.run(1)
+            };
+            assert_eq!(result, Err(PipelineError::Deadlock));
+        }
+
+        for threaded in [false, true].iter() {
+            let pipeline = Pipeline::new(&[3, 0, 42], vec![vec![], vec![]]);
+            let result = if *threaded {
+                pipeline.run_threaded(0)
+            } else {
+                pipeline.run(0)
+            };
+            match result {
+                Err(PipelineError::Failed { machine, .. }) => assert_eq!(machine, 0),
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::disassembler::{self, Statement};
+use crate::{
+    get_index_value, get_target_index, intcode_step, IntcodeReturnType, IntcodeState, Memory,
+    ParamMode, ProgramState, StepResult, Stop,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// The memory an instruction touches, worked out before it runs
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Access {
+    pc: usize,
+    size: usize,
+    mnemonic: &'static str,
+    reads: Vec<usize>,
+    write: Option<usize>,
+}
+
+pub(crate) fn access(intcode_state: &IntcodeState) -> Option<Access> {
+    let pc = intcode_state.index;
+    let instruction = get_index_value(intcode_state, pc).ok()?;
+    let program_state = ProgramState::from_memory_location(instruction).ok()?;
+    let param_modes = program_state.param_modes();
+
+    let mut access = Access {
+        pc,
+        size: param_modes.len() + 1,
+        mnemonic: program_state.mnemonic(),
+        reads: vec![],
+        write: None,
+    };
+    for (i, mode) in param_modes.into_iter().enumerate() {
+        if *mode == ParamMode::Immediate {
+            continue;
+        }
+        let address = get_target_index(intcode_state, pc + i + 1, mode).ok()?;
+        if program_state.write_param() == Some(i) {
+            access.write = Some(address);
+        } else {
+            access.reads.push(address);
+        }
+    }
+
+    Some(access)
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Profiler {
+    executions: Vec<u64>,
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+    covered: Vec<bool>,
+    opcodes: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Coverage {
+    pub executed: Vec<usize>,
+    pub missed: Vec<usize>,
+}
+
+impl Coverage {
+    pub fn is_complete(&self) -> bool {
+        self.missed.is_empty()
+    }
+
+    pub fn ratio(&self) -> f64 {
+        let total = self.executed.len() + self.missed.len();
+        if total == 0 {
+            return 1.0;
+        }
+        self.executed.len() as f64 / total as f64
+    }
+}
+
+fn increment(counts: &mut Vec<u64>, address: usize) {
+    if address >= counts.len() {
+        counts.resize(address + 1, 0);
+    }
+    counts[address] += 1;
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub(crate) fn record(&mut self, access: Access) {
+        increment(&mut self.executions, access.pc);
+        for &address in &access.reads {
+            increment(&mut self.reads, address);
+        }
+        if let Some(address) = access.write {
+            increment(&mut self.writes, address);
+        }
+        let end = access.pc + access.size;
+        if end > self.covered.len() {
+            self.covered.resize(end, false);
+        }
+        self.covered[access.pc..end]
+            .iter_mut()
+            .for_each(|covered| *covered = true);
+        *self.opcodes.entry(access.mnemonic).or_insert(0) += 1;
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn step(&mut self, intcode_state: IntcodeState) -> StepResult {
+        let access = access(&intcode_state);
+        let result = intcode_step(intcode_state);
+
+        let executed = match &result {
+            Ok(_) | Err(Stop::Returned(IntcodeReturnType::Finished(_))) => true,
+            Err(_) => false,
+        };
+        if let (true, Some(access)) = (executed, access) {
+            self.record(access);
+        }
+
+        result
+    }
+
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executions.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn reads(&self, address: usize) -> u64 {
+        self.reads.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn writes(&self, address: usize) -> u64 {
+        self.writes.get(address).copied().unwrap_or(0)
+    }
+
+    pub fn is_covered(&self, address: usize) -> bool {
+        self.covered.get(address).copied().unwrap_or(false)
+    }
+
+    pub fn opcode_counts(&self) -> &BTreeMap<&'static str, u64> {
+        &self.opcodes
+    }
+
+    pub fn total_steps(&self) -> u64 {
+        self.opcodes.values().sum()
+    }
+
+    // The most executed addresses, ties broken by address
+    pub fn hot_spots(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut spots: Vec<(usize, u64)> = self
+            .executions
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, executions)| executions > 0)
+            .collect();
+        spots.sort_by(|(a1, e1), (a2, e2)| e2.cmp(e1).then(a1.cmp(a2)));
+        spots.truncate(count);
+
+        spots
+    }
+
+    // Instructions of the disassembled program that ran or never ran
+    pub fn coverage(&self, code: &Memory) -> Coverage {
+        let (executed, missed) = instruction_addresses(code)
+            .into_iter()
+            .partition(|&address| self.executions(address) > 0);
+
+        Coverage { executed, missed }
+    }
+
+    pub fn report(&self, code: &Memory, hot_spots: usize) -> String {
+        let coverage = self.coverage(code);
+        let mut report = String::new();
+
+        writeln!(report, "steps: {}", self.total_steps()).unwrap();
+        for (mnemonic, count) in &self.opcodes {
+            writeln!(report, "  {:<4}{:>10}", mnemonic, count).unwrap();
+        }
+        writeln!(report, "hot spots:").unwrap();
+        for (address, executions) in self.hot_spots(hot_spots) {
+            let statement = disassembler::statement_at(code, address);
+            writeln!(report, "{:>10}  {}", executions, statement).unwrap();
+        }
+        writeln!(
+            report,
+            "coverage: {} of {} instructions ({:.1}%)",
+            coverage.executed.len(),
+            coverage.executed.len() + coverage.missed.len(),
+            coverage.ratio() * 100.0
+        )
+        .unwrap();
+        for statement in disassembler::disassemble(code) {
+            let marker = match statement {
+                Statement::Instruction { address, .. } if self.executions(address) > 0 => {
+                    self.executions(address).to_string()
+                }
+                Statement::Instruction { .. } => "-".to_string(),
+                Statement::Data { address, .. } if self.is_covered(address) => "+".to_string(),
+                Statement::Data { .. } => String::new(),
+            };
+            writeln!(report, "{:>10}  {}", marker, statement).unwrap();
+        }
+
+        report
+    }
+}
+
+fn instruction_addresses(code: &Memory) -> Vec<usize> {
+    disassembler::disassemble(code)
+        .iter()
+        .filter(|statement| matches!(statement, Statement::Instruction { .. }))
+        .map(Statement::address)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::get_memory_from_file;
+    use crate::run_profiled;
+    use crate::test_programs::countdown;
+
+    #[test]
+    fn test_counts() {
+        let mut profiler = Profiler::new();
+        run_profiled(IntcodeState::with_next_input(countdown(), 3), &mut profiler).unwrap();
+
+        assert_eq!(profiler.executions(0), 1);
+        assert_eq!(profiler.executions(2), 3);
+        assert_eq!(profiler.executions(11), 1);
+        assert_eq!(profiler.reads(100), 9);
+        assert_eq!(profiler.writes(100), 4);
+        assert_eq!(profiler.opcode_counts()["jnz"], 3);
+        assert_eq!(profiler.total_steps(), 11);
+        assert_eq!(profiler.hot_spots(2), vec![(2, 3), (4, 3)]);
+        assert!(profiler.coverage(&countdown()).is_complete());
+    }
+
+    #[test]
+    fn test_waiting_for_input_is_not_counted() {
+        let mut profiler = Profiler::new();
+        run_profiled(IntcodeState::from(countdown()), &mut profiler).unwrap();
+
+        assert_eq!(profiler.total_steps(), 0);
+        assert_eq!(profiler.coverage(&countdown()).missed, vec![0, 2, 4, 8, 11]);
+    }
+
+    #[test]
+    fn test_diagnostic_coverage() {
+        let code = get_memory_from_file("../day5/resources/input");
+        let profile = |system_id| {
+            let mut profiler = Profiler::new();
+            let state = IntcodeState::with_next_input(code.clone(), system_id);
+            run_profiled(state, &mut profiler).unwrap();
+            profiler
+        };
+        let air_conditioner = profile(1);
+        let thermal_radiator = profile(5);
+
+        // Only the thermal radiator controller runs the jump tests
+        assert_eq!(air_conditioner.opcode_counts().get("jnz"), None);
+        assert!(thermal_radiator.opcode_counts()["jnz"] > 0);
+        assert_eq!(thermal_radiator.opcode_counts().get("out"), Some(&1));
+        let covered = |profiler: &Profiler| profiler.coverage(&code).executed.len();
+        assert!(covered(&air_conditioner) < covered(&thermal_radiator));
+
+        let report = thermal_radiator.report(&code, 3);
+        assert!(report.starts_with(&format!("steps: {}\n", thermal_radiator.total_steps())));
+        assert!(report.contains("hot spots:\n"));
+    }
+}
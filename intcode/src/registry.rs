@@ -0,0 +1,652 @@
+use crate::io::{IntcodeIo, QueueIo};
+use crate::word::{saturating_i64, Word};
+use crate::OpMode::*;
+use crate::ProgramState::{Halted, Running};
+use crate::{
+    execute_decoded, get_index_value, get_jump_target, get_target_index,
+    get_value_at_index_location, set_at_index_location, split_instruction, IntcodeError,
+    IntcodeErrorKind, IntcodeResult, IntcodeReturnType, IntcodeState, Outcome, ParamMode,
+    ProgramState,
+};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Role {
+    Read,
+    Write,
+}
+
+// What the machine does after an instruction
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Flow {
+    Next,
+    Jump(usize),
+    Wait,
+    Halt,
+}
+
+pub(crate) struct Builtin {
+    pub(crate) opcode: usize,
+    pub(crate) mnemonic: &'static str,
+    pub(crate) roles: &'static [Role],
+    decode: fn([ParamMode; 3]) -> ProgramState,
+}
+
+const ARITHMETIC: &[Role] = &[Role::Read, Role::Read, Role::Write];
+const JUMP: &[Role] = &[Role::Read, Role::Read];
+
+// The instruction set of the finished intcode computer. Decoding, the tools
+// and `Registry::builtin` all look instructions up here, what they do is
+// implemented once in `process_op_mode`
+pub(crate) const BUILTINS: [Builtin; 10] = [
+    Builtin {
+        opcode: 1,
+        mnemonic: "add",
+        roles: ARITHMETIC,
+        decode: |[a, b, c]| Running(Add(a, b, c)),
+    },
+    Builtin {
+        opcode: 2,
+        mnemonic: "mul",
+        roles: ARITHMETIC,
+        decode: |[a, b, c]| Running(Mul(a, b, c)),
+    },
+    Builtin {
+        opcode: 3,
+        mnemonic: "in",
+        roles: &[Role::Write],
+        decode: |[a, _, _]| Running(Input(a)),
+    },
+    Builtin {
+        opcode: 4,
+        mnemonic: "out",
+        roles: &[Role::Read],
+        decode: |[a, _, _]| Running(Output(a)),
+    },
+    Builtin {
+        opcode: 5,
+        mnemonic: "jnz",
+        roles: JUMP,
+        decode: |[a, b, _]| Running(JumpIfTrue(a, b)),
+    },
+    Builtin {
+        opcode: 6,
+        mnemonic: "jz",
+        roles: JUMP,
+        decode: |[a, b, _]| Running(JumpIfFalse(a, b)),
+    },
+    Builtin {
+        opcode: 7,
+        mnemonic: "lt",
+        roles: ARITHMETIC,
+        decode: |[a, b, c]| Running(LessThan(a, b, c)),
+    },
+    Builtin {
+        opcode: 8,
+        mnemonic: "eq",
+        roles: ARITHMETIC,
+        decode: |[a, b, c]| Running(Equals(a, b, c)),
+    },
+    Builtin {
+        opcode: 9,
+        mnemonic: "arb",
+        roles: &[Role::Read],
+        decode: |[a, _, _]| Running(AdjustRelativeBase(a)),
+    },
+    Builtin {
+        opcode: 99,
+        mnemonic: "hlt",
+        roles: &[],
+        decode: |_| Halted,
+    },
+];
+
+// Every step decodes through here, so the table is indexed instead of searched
+pub(crate) fn builtin(opcode: usize) -> Option<&'static Builtin> {
+    let index = match opcode {
+        1..=9 => opcode - 1,
+        99 => 9,
+        _ => return None,
+    };
+
+    Some(&BUILTINS[index])
+}
+
+impl Builtin {
+    pub(crate) fn decode(&self, modes: [ParamMode; 3]) -> ProgramState {
+        (self.decode)(modes)
+    }
+}
+
+type Handler<W> = Arc<dyn Fn(&mut Context<W>) -> Result<Flow, IntcodeError> + Send + Sync>;
+
+#[derive(Clone)]
+enum Semantics<W> {
+    Builtin(&'static Builtin),
+    Custom(Handler<W>),
+}
+
+#[derive(Clone)]
+pub struct Instruction<W = i64> {
+    mnemonic: &'static str,
+    roles: Vec<Role>,
+    semantics: Semantics<W>,
+}
+
+impl<W> Instruction<W> {
+    pub fn mnemonic(&self) -> &'static str {
+        self.mnemonic
+    }
+
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    pub fn is_builtin(&self) -> bool {
+        matches!(self.semantics, Semantics::Builtin(_))
+    }
+}
+
+impl<W> fmt::Debug for Instruction<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:?}", self.mnemonic, self.roles)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RegistryError {
+    OpcodeOutOfRange(usize),
+    AlreadyRegistered(usize),
+    TooManyParameters(usize),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::OpcodeOutOfRange(opcode) => {
+                write!(f, "opcode {} is not between 1 and 99", opcode)
+            }
+            RegistryError::AlreadyRegistered(opcode) => {
+                write!(f, "opcode {} is already registered", opcode)
+            }
+            RegistryError::TooManyParameters(count) => {
+                write!(f, "{} parameters do not fit the three mode digits", count)
+            }
+        }
+    }
+}
+
+impl Error for RegistryError {}
+
+// The instruction being executed. Parameters are resolved when the handler
+// asks for them, so errors come up in the order the handler uses them
+pub struct Context<'a, W = i64> {
+    state: &'a mut IntcodeState<W>,
+    io: &'a mut dyn IntcodeIo<W>,
+    modes: [ParamMode; 3],
+    roles: &'a [Role],
+}
+
+impl<W: Word> Context<'_, W> {
+    pub fn state(&self) -> &IntcodeState<W> {
+        self.state
+    }
+
+    // Parameters are counted from 0
+    pub fn read(&self, parameter: usize) -> Result<W, IntcodeError> {
+        let mode = self.mode(parameter, Role::Read)?;
+        get_value_at_index_location(self.state, self.state.index + parameter + 1, mode)
+    }
+
+    // The address a write parameter points to
+    pub fn target(&self, parameter: usize) -> Result<usize, IntcodeError> {
+        let mode = self.mode(parameter, Role::Write)?;
+        get_target_index(self.state, self.state.index + parameter + 1, mode)
+    }
+
+    pub fn jump_target(&self, parameter: usize) -> Result<usize, IntcodeError> {
+        let mode = self.mode(parameter, Role::Read)?;
+        get_jump_target(self.state, self.state.index + parameter + 1, mode)
+    }
+
+    fn mode(&self, parameter: usize, role: Role) -> Result<&ParamMode, IntcodeError> {
+        let error = |kind| IntcodeError::at(self.state, self.state.index + parameter + 1, kind);
+        match self.roles.get(parameter) {
+            None => Err(error(IntcodeErrorKind::UndeclaredParameter {
+                arity: self.roles.len(),
+            })),
+            Some(&declared) if declared != role => {
+                Err(error(IntcodeErrorKind::WrongRole { declared }))
+            }
+            Some(_) => Ok(&self.modes[parameter]),
+        }
+    }
+
+    // Writes are held to the memory limit like the ones of the built-ins
+    pub fn store(&mut self, address: usize, value: W) -> Result<(), IntcodeError> {
+        if address >= self.state.memory_limit {
+            return Err(self.error(IntcodeErrorKind::MemoryLimitExceeded {
+                address,
+                limit: self.state.memory_limit,
+            }));
+        }
+        set_at_index_location(&mut self.state.code, address, value);
+        Ok(())
+    }
+
+    pub fn input(&mut self) -> Option<W> {
+        self.io.read()
+    }
+
+    pub fn output(&mut self, value: W) {
+        self.io.write(value)
+    }
+
+    pub fn adjust_relative_base(&mut self, adjustment: i64) -> Result<(), IntcodeError> {
+        self.state.relative_base = self
+            .state
+            .relative_base
+            .checked_add(adjustment)
+            .ok_or_else(|| self.error(IntcodeErrorKind::Overflow))?;
+        Ok(())
+    }
+
+    // An error at the current instruction
+    pub fn error(&self, kind: IntcodeErrorKind) -> IntcodeError {
+        IntcodeError::at(self.state, self.state.index, kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Registry<W = i64> {
+    instructions: Vec<Option<Instruction<W>>>,
+}
+
+impl<W: Word> Default for Registry<W> {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl<W: Word> Registry<W> {
+    pub fn new() -> Registry<W> {
+        Registry {
+            instructions: vec![None; 100],
+        }
+    }
+
+    // The instructions of the finished intcode computer, they run exactly
+    // like they do without a registry
+    pub fn builtin() -> Registry<W> {
+        let mut registry = Registry::new();
+        for builtin in BUILTINS.iter() {
+            registry
+                .insert(
+                    builtin.opcode,
+                    builtin.mnemonic,
+                    builtin.roles.to_vec(),
+                    Semantics::Builtin(builtin),
+                )
+                .unwrap();
+        }
+
+        registry
+    }
+
+    pub fn register<F>(
+        &mut self,
+        opcode: usize,
+        mnemonic: &'static str,
+        roles: &[Role],
+        handler: F,
+    ) -> Result<(), RegistryError>
+    where
+        F: Fn(&mut Context<W>) -> Result<Flow, IntcodeError> + Send + Sync + 'static,
+    {
+        self.insert(
+            opcode,
+            mnemonic,
+            roles.to_vec(),
+            Semantics::Custom(Arc::new(handler)),
+        )
+    }
+
+    fn insert(
+        &mut self,
+        opcode: usize,
+        mnemonic: &'static str,
+        roles: Vec<Role>,
+        semantics: Semantics<W>,
+    ) -> Result<(), RegistryError> {
+        if opcode == 0 || opcode > 99 {
+            return Err(RegistryError::OpcodeOutOfRange(opcode));
+        }
+        if roles.len() > 3 {
+            return Err(RegistryError::TooManyParameters(roles.len()));
+        }
+        if self.instructions[opcode].is_some() {
+            return Err(RegistryError::AlreadyRegistered(opcode));
+        }
+
+        self.instructions[opcode] = Some(Instruction {
+            mnemonic,
+            roles,
+            semantics,
+        });
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, opcode: usize) -> Option<Instruction<W>> {
+        self.instructions.get_mut(opcode)?.take()
+    }
+
+    pub fn get(&self, opcode: usize) -> Option<&Instruction<W>> {
+        self.instructions.get(opcode)?.as_ref()
+    }
+
+    // The opcode of the instruction called `mnemonic`
+    pub fn find(&self, mnemonic: &str) -> Option<(usize, &Instruction<W>)> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .find_map(|(opcode, instruction)| match instruction {
+                Some(instruction) if instruction.mnemonic == mnemonic => {
+                    Some((opcode, instruction))
+                }
+                _ => None,
+            })
+    }
+
+    pub fn run(&self, mut intcode_state: IntcodeState<W>) -> IntcodeResult<W> {
+        let mut io = QueueIo::take_from(&mut intcode_state);
+        let outcome = self.run_in_place(&mut intcode_state, &mut io);
+        let intcode_state = io.put_back(intcode_state);
+
+        Ok(IntcodeReturnType::new(outcome?, intcode_state))
+    }
+
+    pub(crate) fn run_in_place(
+        &self,
+        intcode_state: &mut IntcodeState<W>,
+        io: &mut dyn IntcodeIo<W>,
+    ) -> Result<Outcome, IntcodeError> {
+        loop {
+            match self.execute_step(intcode_state, io)? {
+                Outcome::Continue => {}
+                outcome => return Ok(outcome),
+            }
+        }
+    }
+
+    pub(crate) fn execute_step(
+        &self,
+        intcode_state: &mut IntcodeState<W>,
+        io: &mut dyn IntcodeIo<W>,
+    ) -> Result<Outcome, IntcodeError> {
+        if intcode_state.step_budget == Some(0) {
+            return Ok(Outcome::BudgetExhausted);
+        }
+
+        let pc = intcode_state.index;
+        let word = get_index_value(intcode_state, pc)
+            .map_err(|kind| IntcodeError::at(intcode_state, pc, kind))?;
+        let (opcode, modes) =
+            split_instruction(saturating_i64(&word)).map_err(|error| error.at_pc(pc))?;
+        let instruction = self.get(opcode).ok_or_else(|| {
+            IntcodeError::at(
+                intcode_state,
+                pc,
+                IntcodeErrorKind::UnknownOpcode(opcode as i64),
+            )
+        })?;
+        let handler = match &instruction.semantics {
+            Semantics::Builtin(builtin) => {
+                let (outcome, _) = execute_decoded(intcode_state, builtin.decode(modes), io)?;
+                return Ok(outcome);
+            }
+            Semantics::Custom(handler) => handler,
+        };
+
+        let mut context = Context {
+            state: intcode_state,
+            io,
+            modes,
+            roles: &instruction.roles,
+        };
+        let outcome = match handler(&mut context)? {
+            Flow::Next => {
+                intcode_state.index += instruction.roles.len() + 1;
+                Outcome::Continue
+            }
+            Flow::Jump(target) => {
+                intcode_state.index = target;
+                Outcome::Continue
+            }
+            Flow::Wait => return Ok(Outcome::WaitingForInput),
+            Flow::Halt => return Ok(Outcome::Halted),
+        };
+        if let Some(step_budget) = intcode_state.step_budget.as_mut() {
+            *step_budget -= 1;
+        }
+
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble_with;
+    use crate::disassembler::listing_with;
+    use crate::input::get_memory_from_file;
+    use crate::machine::Machine;
+    use crate::{run_instruction_set_with_input, run_intcode};
+
+    fn with_square() -> Registry {
+        let mut registry: Registry = Registry::builtin();
+        registry
+            .register(10, "sqr", &[Role::Read, Role::Write], |context| {
+                let value = context.read(0)?;
+                let target = context.target(1)?;
+                let square = value
+                    .checked_mul(value)
+                    .ok_or_else(|| context.error(IntcodeErrorKind::Overflow))?;
+                context.store(target, square)?;
+                Ok(Flow::Next)
+            })
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_builtins_run_the_diagnostic() {
+        let code = get_memory_from_file("../day5/resources/input");
+        for &system_id in [1, 5].iter() {
+            let state = IntcodeState::with_next_input(code.clone(), system_id);
+            assert_eq!(
+                Registry::builtin().run(state),
+                run_instruction_set_with_input(code.clone(), system_id)
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_instruction() {
+        // Squares its input and outputs the result
+        let code = vec![3, 9, 10, 9, 9, 4, 9, 99, 0, 0];
+        let mut machine = Machine::new(&code);
+        machine.push_input(12);
+
+        assert_eq!(
+            machine.run_with_registry(&with_square()),
+            Ok(Outcome::Halted)
+        );
+        assert_eq!(machine.output(), &[144]);
+
+        let mut machine = Machine::new(&code);
+        machine.push_input(12);
+        let error = machine.run().unwrap_err();
+        assert_eq!(error.kind, IntcodeErrorKind::UnknownOpcode(10));
+
+        let mut machine = Machine::new(&[1010, 9, 0, 99]);
+        let error = machine.run_with_registry(&with_square()).unwrap_err();
+        assert_eq!(
+            (error.pc, error.parameter, error.kind),
+            (0, Some(2), IntcodeErrorKind::ImmediateWrite)
+        );
+    }
+
+    #[test]
+    fn test_undeclared_parameter() {
+        let mut registry = Registry::builtin();
+        registry
+            .register(10, "bad", &[Role::Read], |context| {
+                context.read(1)?;
+                Ok(Flow::Next)
+            })
+            .unwrap();
+
+        let error = Machine::new(&[10, 0, 99])
+            .run_with_registry(&registry)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "pc 0 (instruction 10), parameter 2: the instruction only has 1 parameters"
+        );
+    }
+
+    #[test]
+    fn test_roles_and_memory_limit() {
+        let mut registry = Registry::builtin();
+        registry
+            .register(10, "rd", &[Role::Read], |context| {
+                context.target(0)?;
+                Ok(Flow::Next)
+            })
+            .unwrap();
+        registry
+            .register(11, "far", &[Role::Read], |context| {
+                let address = context.read(0)? as usize;
+                context.store(address, 1)?;
+                Ok(Flow::Next)
+            })
+            .unwrap();
+
+        let error = Machine::new(&[10, 0, 99])
+            .run_with_registry(&registry)
+            .unwrap_err();
+        assert_eq!(
+            (error.parameter, error.kind),
+            (
+                Some(1),
+                IntcodeErrorKind::WrongRole {
+                    declared: Role::Read
+                }
+            )
+        );
+
+        for &address in [1000, -1].iter() {
+            let state = IntcodeState::from(vec![111, address, 99]).with_memory_limit(100);
+            let mut machine = Machine::from(state);
+            let error = machine.run_with_registry(&registry).unwrap_err();
+            assert_eq!(
+                error.kind,
+                IntcodeErrorKind::MemoryLimitExceeded {
+                    address: address as usize,
+                    limit: 100
+                }
+            );
+            assert_eq!(machine.memory().len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_registration_errors() {
+        let mut registry = with_square();
+        let noop = |_: &mut Context| Ok(Flow::Next);
+
+        assert_eq!(
+            registry.register(10, "dup", &[], noop),
+            Err(RegistryError::AlreadyRegistered(10))
+        );
+        assert_eq!(
+            registry.register(100, "big", &[], noop),
+            Err(RegistryError::OpcodeOutOfRange(100))
+        );
+        assert_eq!(
+            registry.register(11, "wide", &[Role::Read; 4], noop),
+            Err(RegistryError::TooManyParameters(4))
+        );
+
+        assert_eq!(registry.unregister(10).unwrap().mnemonic(), "sqr");
+        assert_eq!(
+            registry.get(1).unwrap().roles(),
+            &[Role::Read, Role::Read, Role::Write]
+        );
+        assert!(registry.register(10, "nop", &[], noop).is_ok());
+    }
+
+    #[test]
+    fn test_decoding_matches_the_table() {
+        let modes = [ParamMode::Relative; 3];
+        for builtin in BUILTINS.iter() {
+            assert_eq!(
+                super::builtin(builtin.opcode).unwrap().mnemonic,
+                builtin.mnemonic
+            );
+            let program_state = builtin.decode(modes);
+            assert_eq!(program_state.opcode(), builtin.opcode);
+            assert_eq!(program_state.mnemonic(), builtin.mnemonic);
+            assert_eq!(program_state.param_modes().len(), builtin.roles.len());
+            assert_eq!(
+                ProgramState::from_memory_location(builtin.opcode as i64),
+                Ok(builtin.decode([ParamMode::Position; 3]))
+            );
+        }
+    }
+
+    #[test]
+    fn test_tools_see_registered_instructions() {
+        let source = "in [9]\nsqr [9], [9]\nout [9]\nhlt\n";
+        let code = assemble_with(source, &with_square()).unwrap();
+        assert_eq!(code, vec![3, 9, 10, 9, 9, 4, 9, 99]);
+        assert_eq!(
+            listing_with(&code, &with_square()),
+            "    0: in [9]\n\
+             \x20   2: sqr [9], [9]\n\
+             \x20   5: out [9]\n\
+             \x20   7: hlt\n"
+        );
+        assert!(crate::assembler::assemble(source).is_err());
+    }
+
+    #[test]
+    fn test_wide_words() {
+        let mut registry = Registry::<i128>::builtin();
+        registry
+            .register(10, "sqr", &[Role::Read, Role::Write], |context| {
+                let value = context.read(0)?;
+                let target = context.target(1)?;
+                let square = value
+                    .checked_mul(value)
+                    .ok_or_else(|| context.error(IntcodeErrorKind::Overflow))?;
+                context.store(target, square)?;
+                Ok(Flow::Next)
+            })
+            .unwrap();
+
+        let code: Vec<i128> = vec![1102, 1 << 40, 1, 11, 10, 11, 11, 4, 11, 99, 0, 0];
+        match registry.run(IntcodeState::new(code)) {
+            Ok(IntcodeReturnType::Finished(state)) => assert_eq!(state.output, vec![1 << 80]),
+            result => panic!("wrong enum variant {:?}", result),
+        }
+
+        let code: Vec<i128> = vec![109, 1 << 70, 109, -(1 << 70), 204, 7, 99, 42];
+        assert_eq!(
+            registry.run(IntcodeState::new(code.clone())),
+            run_intcode(IntcodeState::new(code))
+        );
+    }
+}
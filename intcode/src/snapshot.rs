@@ -0,0 +1,195 @@
+use crate::{IntcodeState, Memory};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+// Bump when the layout of the snapshot changes and keep a conversion from
+// every older layout in `from_version`
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotV1 {
+    code: Memory,
+    index: usize,
+    input: VecDeque<i64>,
+    output: Vec<i64>,
+    relative_base: i64,
+    memory_limit: usize,
+    step_budget: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct JsonSnapshot<'a> {
+    version: u32,
+    state: &'a SnapshotV1,
+}
+
+#[derive(Deserialize)]
+struct JsonEnvelope {
+    version: u32,
+    state: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Json(serde_json::Error),
+    Binary(bincode::Error),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Json(error) => write!(f, "invalid json snapshot: {}", error),
+            SnapshotError::Binary(error) => write!(f, "invalid binary snapshot: {}", error),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> Self {
+        SnapshotError::Json(error)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(error: bincode::Error) -> Self {
+        SnapshotError::Binary(error)
+    }
+}
+
+impl From<&IntcodeState> for SnapshotV1 {
+    fn from(intcode_state: &IntcodeState) -> Self {
+        SnapshotV1 {
+            code: intcode_state.code.clone(),
+            index: intcode_state.index,
+            input: intcode_state.input.clone(),
+            output: intcode_state.output.clone(),
+            relative_base: intcode_state.relative_base,
+            memory_limit: intcode_state.memory_limit,
+            step_budget: intcode_state.step_budget,
+        }
+    }
+}
+
+impl From<SnapshotV1> for IntcodeState {
+    fn from(snapshot: SnapshotV1) -> Self {
+        IntcodeState {
+            code: snapshot.code,
+            index: snapshot.index,
+            input: snapshot.input,
+            output: snapshot.output,
+            relative_base: snapshot.relative_base,
+            memory_limit: snapshot.memory_limit,
+            step_budget: snapshot.step_budget,
+        }
+    }
+}
+
+impl IntcodeState {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        let snapshot = JsonSnapshot {
+            version: SNAPSHOT_VERSION,
+            state: &SnapshotV1::from(self),
+        };
+
+        Ok(serde_json::to_string(&snapshot)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<IntcodeState, SnapshotError> {
+        let envelope: JsonEnvelope = serde_json::from_str(json)?;
+
+        match envelope.version {
+            1 => Ok(serde_json::from_value::<SnapshotV1>(envelope.state)?.into()),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        Ok(bincode::serialize(&(
+            SNAPSHOT_VERSION,
+            SnapshotV1::from(self),
+        ))?)
+    }
+
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<IntcodeState, SnapshotError> {
+        let version: u32 = bincode::deserialize_from(&mut bytes)?;
+
+        match version {
+            1 => Ok(bincode::deserialize::<SnapshotV1>(bytes)?.into()),
+            version => Err(SnapshotError::UnsupportedVersion(version)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+    use crate::{intcode_step, IntcodeReturnType, Stop};
+
+    // Adds two inputs and outputs the sum, stopped while waiting for the second one
+    fn interrupted_adder() -> IntcodeState {
+        let mut state = IntcodeState::with_next_input(adder(), 3)
+            .with_memory_limit(1000)
+            .with_step_budget(50);
+        state.relative_base = 5;
+        loop {
+            state = match intcode_step(state) {
+                Ok(state) => state,
+                Err(Stop::Returned(IntcodeReturnType::Interrupted(state))) => return state,
+                Err(stop) => panic!("wrong enum variant {:?}", stop),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let state = interrupted_adder();
+        let restored = IntcodeState::from_json(&state.to_json().unwrap()).unwrap();
+
+        assert_eq!(restored, state);
+        assert_eq!(restored.index(), 2);
+        assert_eq!(restored.relative_base(), 5);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let state = interrupted_adder();
+        let bytes = state.to_bytes().unwrap();
+
+        assert_eq!(bytes[..4], SNAPSHOT_VERSION.to_le_bytes());
+        assert_eq!(IntcodeState::from_bytes(&bytes).unwrap(), state);
+    }
+
+    #[test]
+    fn test_json_format() {
+        let state = IntcodeState::with_next_input(vec![3, 0, 99], 7);
+
+        assert_eq!(
+            state.to_json().unwrap(),
+            r#"{"version":1,"state":{"code":[3,0,99],"index":0,"input":[7],"output":[],"relative_base":0,"memory_limit":16777216,"step_budget":null}}"#
+        );
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let json = r#"{"version":2,"state":{}}"#;
+        assert!(matches!(
+            IntcodeState::from_json(json),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+
+        let bytes = bincode::serialize(&7u32).unwrap();
+        assert!(matches!(
+            IntcodeState::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(7))
+        ));
+    }
+}
@@ -0,0 +1,12 @@
+// Small programs shared by the unit tests
+use crate::Memory;
+
+// Adds two inputs and outputs the sum
+pub(crate) fn adder() -> Memory {
+    vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99, 0, 0, 0]
+}
+
+// Reads a number, then counts it down to zero and outputs every value
+pub(crate) fn countdown() -> Memory {
+    vec![3, 100, 4, 100, 1001, 100, -1, 100, 1005, 100, 2, 99]
+}
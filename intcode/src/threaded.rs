@@ -0,0 +1,302 @@
+use crate::io::IntcodeIo;
+use crate::{run_in_place, IntcodeError, IntcodeState, Outcome};
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+// Reading blocks until a value arrives. Once every sender is gone the read
+// fails and the machine stops with `Outcome::WaitingForInput`
+pub struct ChannelIo {
+    input: Receiver<i64>,
+    output: Sender<i64>,
+    detector: Option<Arc<DeadlockDetector>>,
+    pub written: Vec<i64>,
+}
+
+impl ChannelIo {
+    pub fn new(input: Receiver<i64>, output: Sender<i64>) -> ChannelIo {
+        ChannelIo {
+            input,
+            output,
+            detector: None,
+            written: vec![],
+        }
+    }
+}
+
+impl IntcodeIo for ChannelIo {
+    fn read(&mut self) -> Option<i64> {
+        match &self.detector {
+            Some(detector) => detector.recv(&self.input),
+            None => self.input.recv().ok(),
+        }
+    }
+
+    fn write(&mut self, value: i64) {
+        self.written.push(value);
+        match &self.detector {
+            Some(detector) => detector.send(&self.output, value),
+            // the receiving machine may already have halted
+            None => {
+                let _ = self.output.send(value);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Machines {
+    running: usize,
+    blocked: usize,
+    // Bumped whenever a value is sent or a machine stops, blocked machines
+    // then have to look at their channel again before they count
+    generation: u64,
+    armed: bool,
+    deadlocked: bool,
+}
+
+// Sends and blocking reads of a closed network go through one lock, so the
+// network is deadlocked exactly when every running machine has seen its
+// channel empty since the last value was sent
+#[derive(Default)]
+struct DeadlockDetector {
+    machines: Mutex<Machines>,
+    changed: Condvar,
+}
+
+impl DeadlockDetector {
+    fn recv(&self, input: &Receiver<i64>) -> Option<i64> {
+        let mut machines = self.machines.lock().unwrap();
+        let mut counted_in = None;
+
+        loop {
+            match input.try_recv() {
+                Ok(value) => {
+                    if counted_in == Some(machines.generation) {
+                        machines.blocked -= 1;
+                    }
+                    return Some(value);
+                }
+                Err(TryRecvError::Disconnected) => return None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if machines.deadlocked {
+                return None;
+            }
+            if counted_in != Some(machines.generation) {
+                machines.blocked += 1;
+                counted_in = Some(machines.generation);
+            }
+            if machines.armed && machines.blocked == machines.running {
+                machines.deadlocked = true;
+                self.changed.notify_all();
+                return None;
+            }
+            machines = self.changed.wait(machines).unwrap();
+        }
+    }
+
+    fn send(&self, output: &Sender<i64>, value: i64) {
+        let mut machines = self.machines.lock().unwrap();
+        // the receiving machine may already have halted
+        let _ = output.send(value);
+        self.update(&mut machines, |_| {});
+    }
+
+    fn update(&self, machines: &mut Machines, change: impl FnOnce(&mut Machines)) {
+        change(machines);
+        machines.generation += 1;
+        machines.blocked = 0;
+        self.changed.notify_all();
+    }
+
+    fn started(&self) {
+        let mut machines = self.machines.lock().unwrap();
+        self.update(&mut machines, |machines| machines.running += 1);
+    }
+
+    fn stopped(&self) {
+        let mut machines = self.machines.lock().unwrap();
+        self.update(&mut machines, |machines| machines.running -= 1);
+    }
+
+    fn arm(&self) {
+        let mut machines = self.machines.lock().unwrap();
+        self.update(&mut machines, |machines| machines.armed = true);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MachineReport {
+    pub state: IntcodeState,
+    pub result: Result<Outcome, IntcodeError>,
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    handles: Vec<JoinHandle<MachineReport>>,
+    detector: Option<Arc<DeadlockDetector>>,
+}
+
+impl Supervisor {
+    pub fn new() -> Supervisor {
+        Supervisor::default()
+    }
+
+    // For networks whose machines only read what other machines of the
+    // network, or the caller before `spawn`, sent them. Once `join` is
+    // called and every running machine waits for input, they all stop with
+    // `Outcome::WaitingForInput` instead of blocking forever
+    pub fn detecting_deadlocks() -> Supervisor {
+        Supervisor {
+            detector: Some(Arc::default()),
+            ..Supervisor::default()
+        }
+    }
+
+    // The inputs already queued in `state` are read before the channel.
+    // Returns the index of the machine's report in `join`
+    pub fn spawn(&mut self, mut state: IntcodeState, mut io: ChannelIo) -> usize {
+        if let Some(detector) = &self.detector {
+            detector.started();
+            io.detector = Some(detector.clone());
+        }
+        let handle = thread::spawn(move || {
+            let detector = io.detector.clone();
+            let mut queued = mem::take(&mut state.input);
+            let mut io = QueuedChannelIo {
+                queued: &mut queued,
+                io: &mut io,
+            };
+            let result = run_in_place(&mut state, &mut io);
+            state.output.append(&mut io.io.written);
+            state.input = queued;
+            if let Some(detector) = detector {
+                detector.stopped();
+            }
+
+            MachineReport { state, result }
+        });
+        self.handles.push(handle);
+
+        self.handles.len() - 1
+    }
+
+    // Blocks until every machine has stopped. A panic in a machine thread is
+    // propagated to the caller
+    pub fn join(self) -> Vec<MachineReport> {
+        if let Some(detector) = &self.detector {
+            detector.arm();
+        }
+        self.handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    }
+}
+
+struct QueuedChannelIo<'a> {
+    queued: &'a mut VecDeque<i64>,
+    io: &'a mut ChannelIo,
+}
+
+impl IntcodeIo for QueuedChannelIo<'_> {
+    fn read(&mut self) -> Option<i64> {
+        self.queued.pop_front().or_else(|| self.io.read())
+    }
+
+    fn write(&mut self, value: i64) {
+        self.io.write(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_programs::adder;
+    use crate::IntcodeErrorKind;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_machines_talk_over_channels() {
+        let (first_in, first_rx) = channel();
+        let (second_in, second_rx) = channel();
+        let (result_tx, result_rx) = channel();
+
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn(
+            IntcodeState::with_next_input(adder(), 1),
+            ChannelIo::new(first_rx, second_in.clone()),
+        );
+        supervisor.spawn(
+            IntcodeState::from(adder()),
+            ChannelIo::new(second_rx, result_tx),
+        );
+        second_in.send(10).unwrap();
+        drop(second_in);
+        first_in.send(2).unwrap();
+
+        assert_eq!(result_rx.recv(), Ok(13));
+        let reports = supervisor.join();
+        assert_eq!(reports[0].result, Ok(Outcome::Halted));
+        assert_eq!(reports[0].state.output, vec![3]);
+        assert_eq!(reports[1].state.output, vec![13]);
+    }
+
+    #[test]
+    fn test_reports_errors_and_starved_machines() {
+        let (tx, rx) = channel();
+        let (output, _) = channel();
+        let mut supervisor = Supervisor::new();
+        supervisor.spawn(
+            IntcodeState::from(vec![42]),
+            ChannelIo::new(rx, output.clone()),
+        );
+        let (starved_tx, starved_rx) = channel::<i64>();
+        supervisor.spawn(
+            IntcodeState::from(adder()),
+            ChannelIo::new(starved_rx, output),
+        );
+        drop(tx);
+        drop(starved_tx);
+
+        let reports = supervisor.join();
+        assert_eq!(
+            reports[0].result.as_ref().unwrap_err().kind,
+            IntcodeErrorKind::UnknownOpcode(42)
+        );
+        assert_eq!(reports[1].result, Ok(Outcome::WaitingForInput));
+    }
+
+    #[test]
+    fn test_detects_deadlocks() {
+        // The second adder gets one value from the first, which then halts.
+        // The caller still holds both senders, so the channels stay open
+        let (first_in, first_rx) = channel();
+        let (second_in, second_rx) = channel();
+
+        let mut supervisor = Supervisor::detecting_deadlocks();
+        supervisor.spawn(
+            IntcodeState::from(adder()),
+            ChannelIo::new(first_rx, second_in.clone()),
+        );
+        supervisor.spawn(
+            IntcodeState::from(adder()),
+            ChannelIo::new(second_rx, first_in.clone()),
+        );
+        first_in.send(1).unwrap();
+        first_in.send(2).unwrap();
+
+        let reports = supervisor.join();
+        assert_eq!(reports[0].result, Ok(Outcome::Halted));
+        assert_eq!(reports[1].result, Ok(Outcome::WaitingForInput));
+        assert_eq!(reports[0].state.output, vec![3]);
+        assert!(reports[1].state.output.is_empty());
+    }
+}
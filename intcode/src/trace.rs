@@ -0,0 +1,292 @@
+use crate::{
+    get_index_value, get_target_index, get_value_at_index_location, intcode_step,
+    IntcodeReturnType, IntcodeState, ProgramState, StepResult, Stop,
+};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TraceStep {
+    pub step: u64,
+    pub pc: usize,
+    pub instruction: i64,
+    pub mnemonic: Option<&'static str>,
+    pub operands: Vec<TraceOperand>,
+    pub writes: Vec<MemoryWrite>,
+    pub io: Vec<IoEvent>,
+    pub relative_base: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct TraceOperand {
+    pub mode: &'static str,
+    pub raw: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<i64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub value: i64,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IoEvent {
+    Input(i64),
+    Output(i64),
+}
+
+pub trait Tracer {
+    fn trace(&mut self, step: &TraceStep);
+}
+
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(writer: W) -> JsonLinesTracer<W> {
+        JsonLinesTracer {
+            writer,
+            error: None,
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, step: &TraceStep) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let result = serde_json::to_writer(&mut self.writer, step)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+}
+
+pub struct RingBufferTracer {
+    capacity: usize,
+    steps: VecDeque<TraceStep>,
+}
+
+impl RingBufferTracer {
+    pub fn new(capacity: usize) -> RingBufferTracer {
+        RingBufferTracer {
+            capacity,
+            steps: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn steps(&self) -> &VecDeque<TraceStep> {
+        &self.steps
+    }
+}
+
+impl Tracer for RingBufferTracer {
+    fn trace(&mut self, step: &TraceStep) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(step.clone());
+    }
+}
+
+pub(crate) fn traced_step(
+    intcode_state: IntcodeState,
+    step: u64,
+) -> (StepResult, Option<TraceStep>) {
+    let pc = intcode_state.index;
+    let instruction = get_index_value(&intcode_state, pc).unwrap_or(0);
+    let program_state = ProgramState::from_memory_location(instruction).ok();
+
+    let mut trace_step = TraceStep {
+        step,
+        pc,
+        instruction,
+        mnemonic: program_state.as_ref().map(ProgramState::mnemonic),
+        operands: vec![],
+        writes: vec![],
+        io: vec![],
+        relative_base: intcode_state.relative_base,
+        error: None,
+    };
+    let mut write_address = None;
+
+    if let Some(program_state) = &program_state {
+        for (i, mode) in program_state.param_modes().into_iter().enumerate() {
+            let index = pc + i + 1;
+            let address = get_target_index(&intcode_state, index, mode).ok();
+            let is_write = program_state.write_param() == Some(i);
+            if is_write {
+                write_address = address;
+            }
+
+            trace_step.operands.push(TraceOperand {
+                mode: mode.name(),
+                raw: get_index_value(&intcode_state, index).unwrap_or(0),
+                address,
+                value: if is_write {
+                    None
+                } else {
+                    get_value_at_index_location(&intcode_state, index, mode).ok()
+                },
+            });
+        }
+    }
+
+    let next_input = intcode_state.input.front().copied();
+    let output_len = intcode_state.output.len();
+    let input_len = intcode_state.input.len();
+
+    let result = intcode_step(intcode_state);
+
+    match &result {
+        Ok(new_state) => {
+            if let Some(address) = write_address {
+                trace_step.writes.push(MemoryWrite {
+                    address,
+                    value: new_state.code[address],
+                });
+            }
+            if let (Some(input), true) = (next_input, new_state.input.len() < input_len) {
+                trace_step.io.push(IoEvent::Input(input));
+            }
+            for output in &new_state.output[output_len..] {
+                trace_step.io.push(IoEvent::Output(*output));
+            }
+        }
+        Err(Stop::Returned(IntcodeReturnType::Finished(_))) => {}
+        Err(Stop::Returned(IntcodeReturnType::Interrupted(_)))
+        | Err(Stop::Returned(IntcodeReturnType::BudgetExhausted(_))) => return (result, None),
+        Err(Stop::Failed(error)) => trace_step.error = Some(error.to_string()),
+    }
+
+    (result, Some(trace_step))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run_traced;
+    use crate::test_programs::adder;
+
+    #[test]
+    fn test_ring_buffer_keeps_last_steps() {
+        let mut tracer = RingBufferTracer::new(2);
+        run_traced(IntcodeState::with_inputs(adder(), vec![3, 4]), &mut tracer).unwrap();
+
+        let steps: Vec<_> = tracer.steps().iter().map(|s| (s.step, s.pc)).collect();
+        assert_eq!(steps, vec![(3, 8), (4, 10)]);
+    }
+
+    #[test]
+    fn test_trace_step_contents() {
+        let mut tracer = RingBufferTracer::new(10);
+        run_traced(IntcodeState::with_inputs(adder(), vec![3, 4]), &mut tracer).unwrap();
+
+        assert_eq!(
+            tracer.steps()[2],
+            TraceStep {
+                step: 2,
+                pc: 4,
+                instruction: 1,
+                mnemonic: Some("add"),
+                operands: vec![
+                    TraceOperand {
+                        mode: "position",
+                        raw: 11,
+                        address: Some(11),
+                        value: Some(3)
+                    },
+                    TraceOperand {
+                        mode: "position",
+                        raw: 12,
+                        address: Some(12),
+                        value: Some(4)
+                    },
+                    TraceOperand {
+                        mode: "position",
+                        raw: 13,
+                        address: Some(13),
+                        value: None
+                    },
+                ],
+                writes: vec![MemoryWrite {
+                    address: 13,
+                    value: 7
+                }],
+                io: vec![],
+                relative_base: 0,
+                error: None,
+            }
+        );
+        assert_eq!(tracer.steps()[0].io, vec![IoEvent::Input(3)]);
+        assert_eq!(tracer.steps()[3].io, vec![IoEvent::Output(7)]);
+        assert_eq!(tracer.steps()[4].mnemonic, Some("hlt"));
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let mut tracer = JsonLinesTracer::new(vec![]);
+        run_traced(
+            IntcodeState::from(vec![104, 5, 21101, 1, 2, 1, 99]),
+            &mut tracer,
+        )
+        .unwrap();
+        let output = String::from_utf8(tracer.finish().unwrap()).unwrap();
+
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"step":0,"pc":0,"instruction":104,"mnemonic":"out","operands":[{"mode":"immediate","raw":5,"value":5}],"writes":[],"io":[{"output":5}],"relative_base":0}"#,
+                r#"{"step":1,"pc":2,"instruction":21101,"mnemonic":"add","operands":[{"mode":"immediate","raw":1,"value":1},{"mode":"immediate","raw":2,"value":2},{"mode":"relative","raw":1,"address":1}],"writes":[{"address":1,"value":3}],"io":[],"relative_base":0}"#,
+                r#"{"step":2,"pc":6,"instruction":99,"mnemonic":"hlt","operands":[],"writes":[],"io":[],"relative_base":0}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_records_failing_instruction() {
+        let mut tracer = RingBufferTracer::new(10);
+        let result = run_traced(IntcodeState::from(vec![1, -1, 0, 0]), &mut tracer);
+
+        assert!(result.is_err());
+        assert_eq!(tracer.steps().len(), 1);
+        assert_eq!(
+            tracer.steps()[0].error,
+            Some("pc 0 (instruction 1), parameter 1: negative address -1".to_string())
+        );
+        assert_eq!(tracer.steps()[0].operands[0].value, None);
+    }
+
+    #[test]
+    fn test_interrupt_is_not_traced() {
+        let mut tracer = RingBufferTracer::new(10);
+        let result = run_traced(IntcodeState::from(vec![3, 0, 99]), &mut tracer);
+
+        assert!(matches!(result, Ok(IntcodeReturnType::Interrupted(_))));
+        assert!(tracer.steps().is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+use std::convert::TryFrom;
+use std::fmt::Debug;
+
+// A memory cell of the machine. Arithmetic is checked, so a program that
+// leaves the range of its word type fails instead of wrapping
+pub trait Word: Clone + PartialEq + PartialOrd + Debug + From<i64> {
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn to_i64(&self) -> Option<i64>;
+}
+
+impl Word for i64 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+}
+
+impl Word for i128 {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(*self).ok()
+    }
+}
+
+#[cfg(feature = "bigint")]
+impl Word for num_bigint::BigInt {
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+}
+
+// Opcodes and error messages only need the i64 range, larger words are
+// clamped to it. Addresses that don't fit are an overflow instead
+pub(crate) fn saturating_i64<W: Word>(word: &W) -> i64 {
+    word.to_i64().unwrap_or(if *word < W::from(0) {
+        i64::MIN
+    } else {
+        i64::MAX
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        run_instruction_set, run_intcode, IntcodeErrorKind, IntcodeReturnType, IntcodeState,
+    };
+
+    // Squares the word at address 13 three times, multiplying it by itself
+    fn square_three_times(start: i64) -> Vec<i64> {
+        vec![2, 13, 13, 13, 2, 13, 13, 13, 2, 13, 13, 13, 99, start]
+    }
+
+    fn final_value<W: super::Word>(code: Vec<W>) -> W {
+        match run_intcode(IntcodeState::<W>::new(code)) {
+            Ok(IntcodeReturnType::Finished(state)) => state.code[13].clone(),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        let error = run_instruction_set(square_three_times(1 << 10)).unwrap_err();
+        assert_eq!(error.kind, IntcodeErrorKind::Overflow);
+        assert_eq!(error.pc, 8);
+        assert_eq!(
+            error.to_string(),
+            "pc 8 (instruction 2): arithmetic overflow"
+        );
+
+        let error = run_instruction_set(vec![1101, i64::MAX, 1, 0, 99]).unwrap_err();
+        assert_eq!((error.pc, error.kind), (0, IntcodeErrorKind::Overflow));
+
+        let error = run_instruction_set(vec![109, i64::MAX, 109, 1, 99]).unwrap_err();
+        assert_eq!((error.pc, error.kind), (2, IntcodeErrorKind::Overflow));
+    }
+
+    #[test]
+    fn test_wide_words() {
+        let code = square_three_times(1 << 10);
+        assert_eq!(final_value::<i64>(square_three_times(1 << 7)), 1 << 56);
+        assert_eq!(
+            final_value(code.iter().map(|&word| word as i128).collect()),
+            1i128 << 80
+        );
+
+        // Addresses still have to fit an i64
+        let error = run_intcode(IntcodeState::<i128>::new(vec![4, 1 << 70])).unwrap_err();
+        assert_eq!(
+            (error.pc, error.parameter, error.kind),
+            (0, Some(1), IntcodeErrorKind::Overflow)
+        );
+
+        let error = run_intcode(IntcodeState::<i128>::new(vec![1105, 1, 1 << 70])).unwrap_err();
+        assert_eq!(
+            (error.pc, error.parameter, error.kind),
+            (0, Some(2), IntcodeErrorKind::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_wide_relative_base_adjustment() {
+        // Moving the relative base out of the i64 range and back must not
+        // clamp it on the way
+        let code: Vec<i128> = vec![109, 1 << 70, 109, -(1 << 70), 204, 7, 99, 42];
+        let error = run_intcode(IntcodeState::new(code)).unwrap_err();
+        assert_eq!((error.pc, error.kind), (0, IntcodeErrorKind::Overflow));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_bigint_words() {
+        use num_bigint::BigInt;
+
+        let code = square_three_times(1 << 20);
+        let value = final_value(code.into_iter().map(BigInt::from).collect());
+        assert_eq!(value, BigInt::from(1) << 160);
+    }
+}